@@ -1,9 +1,22 @@
 use rocket::{Build, Rocket};
+use rocket::data::Limits;
 
 pub(crate) mod v1;
 
-pub(crate) fn init() -> Rocket<Build> {
+pub(crate) fn init(namespaces: &[String]) -> Rocket<Build> {
 
-    rocket::build()
-        .mount("/races/api/v1", v1::routes())
+    // uploads are far larger than the 8 KiB Rocket reads by default, their limits can still be set
+    // like any other, with `ROCKET_LIMITS` or `Rocket.toml`
+    let limits = Limits::new()
+        .limit(v1::BUNDLE_LIMIT, v1::DEFAULT_BUNDLE_LIMIT.into())
+        .limit(v1::WAYPOINTS_LIMIT, v1::DEFAULT_WAYPOINTS_LIMIT.into());
+    let figment = rocket::Config::figment().join(("limits", limits));
+
+    let mut rocket = rocket::custom(figment)
+        .mount(v1::BASE, v1::routes());
+
+    for namespace in namespaces {
+        rocket = rocket.mount(format!("{}/{}", v1::BASE, namespace), v1::routes());
+    }
+    rocket
 }
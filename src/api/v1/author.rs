@@ -0,0 +1,17 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+/// Author of a change, as given by the `X-Author` header.
+pub(crate) struct Author(pub(crate) Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Author {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let author = request.headers().get_one("X-Author")
+            .map(|author| author.trim().to_string())
+            .filter(|author| !author.is_empty());
+        Outcome::Success(Author(author))
+    }
+}
@@ -0,0 +1,43 @@
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+
+/// Revisions listed in the `If-Match` header, `None` when absent or `*`.
+pub(crate) struct IfMatch(Option<Vec<String>>);
+
+impl IfMatch {
+    pub(crate) fn revisions(&self) -> Option<&[String]> {
+        self.0.as_deref()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let values: Vec<&str> = request.headers().get("If-Match").collect();
+        if values.is_empty() || values.iter().any(|v| v.trim() == "*") {
+            return Outcome::Success(IfMatch(None));
+        }
+
+        let revisions = values.iter()
+            .flat_map(|v| v.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/").trim_matches('"').to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Outcome::Success(IfMatch(Some(revisions)))
+    }
+}
+
+/// Adds the `ETag` header of a race revision to a response.
+pub(crate) struct Tagged<R>(pub(crate) R, pub(crate) String);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+        Response::build_from(self.0.respond_to(request)?)
+            .header(Header::new("ETag", format!("\"{}\"", self.1)))
+            .ok()
+    }
+}
@@ -0,0 +1,42 @@
+use rocket::request::FromParam;
+
+use crate::race::RaceId;
+
+/// Formats a race can be downloaded in, given by the extension of its path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FileFormat {
+    GeoJson,
+    Gpx,
+    Kml,
+    Kmz,
+}
+
+impl FileFormat {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "geojson" => Some(FileFormat::GeoJson),
+            "gpx" => Some(FileFormat::Gpx),
+            "kml" => Some(FileFormat::Kml),
+            "kmz" => Some(FileFormat::Kmz),
+            _ => None,
+        }
+    }
+}
+
+/// `<race_id>.<extension>` path segment of a race downloaded in another format. Segments without
+/// a known extension don't match, so that the route forwards to the plain race one.
+pub(crate) struct RaceFile {
+    pub(crate) race_id: RaceId,
+    pub(crate) format: FileFormat,
+}
+
+impl<'a> FromParam<'a> for RaceFile {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        let (race_id, extension) = param.rsplit_once('.').ok_or(param)?;
+        let format = FileFormat::from_extension(extension).ok_or(param)?;
+        let race_id = RaceId::parse(race_id).map_err(|_| param)?;
+        Ok(RaceFile { race_id, format })
+    }
+}
@@ -1,126 +1,731 @@
+mod author;
+mod etag;
+mod file;
 mod model;
+mod namespace;
+mod rejection;
 
-use rocket::{delete, get, post, put, Route, routes, State};
-use rocket::http::Status;
+use rocket::{delete, get, post, put, Responder, Route, routes, State};
+use rocket::data::{Data, Limits};
+use rocket::form;
+use rocket::http::{Header, Status};
 use rocket::serde::json::Json;
 
+use author::Author;
+use etag::{IfMatch, Tagged};
+use file::{FileFormat, RaceFile};
 use model::leg::Leg;
-use crate::api::v1::model::race::Race;
+use namespace::Namespaced;
+use rejection::Rejection;
+use crate::api::v1::model::bundle::ImportResult;
+use crate::api::v1::model::course::Course;
+use crate::api::v1::model::crossing::{Passage, TrackPoint};
+use crate::api::v1::model::history::{Commit, RaceDiff, Revision};
+use crate::api::v1::model::limits::LimitsEvaluation;
+use crate::api::v1::model::progress::Progress;
+use crate::api::v1::model::race::{LatLon, Race};
+use crate::api::v1::model::spatial::AreaQuery;
+use crate::api::v1::model::trash::TrashEntry;
+use crate::api::v1::model::zone::Intersections;
+use crate::bundle::ConflictPolicy;
+use crate::geojson;
+use crate::gpx;
+use crate::kml;
 use crate::polar::PolarService;
 use crate::race;
-use crate::race::{RaceError, RaceService};
+use crate::race::{RaceError, RaceId};
+use crate::simplify;
+use crate::spatial::Area;
+
+/// Mount point of the routes of the default catalogue, namespaces being mounted below it.
+pub(crate) const BASE: &str = "/races/api/v1";
+
+/// Name of the limit of the size of an imported bundle.
+pub(crate) const BUNDLE_LIMIT: &str = "bundle";
+pub(crate) const DEFAULT_BUNDLE_LIMIT: u64 = 64 << 20;
+/// Name of the limit of the size of a GPX file of waypoints.
+pub(crate) const WAYPOINTS_LIMIT: &str = "waypoints";
+pub(crate) const DEFAULT_WAYPOINTS_LIMIT: u64 = 16 << 20;
 
 pub(crate) fn routes() -> Vec<Route> {
-    routes![list, get, post, put, delete, archive, restore, post_leg]
+    routes![list, get, post, put, delete, archive, restore, post_leg, revisions, revision, diff, rollback,
+        trash, untrash, purge, purge_trash, commits, race_commits, export, import,
+        course, get_file, put_waypoints, zones, limits, crossings, progress]
+}
+
+/// Value of an optional query parameter, `None` when absent, but an error when invalid rather than
+/// ignored as with an `Option`.
+fn optional<T>(value: form::Result<'_, T>) -> Result<Option<T>, Status> {
+    match value {
+        Ok(value) => Ok(Some(value)),
+        Err(errors) if errors.iter().all(|e| matches!(e.kind, form::error::ErrorKind::Missing)) => Ok(None),
+        Err(_) => Err(Status::BadRequest),
+    }
+}
+
+/// Body of an upload, read up to the limit named `limit`, or 413 when larger.
+async fn body(data: Data<'_>, limits: &Limits, limit: &str, default: u64) -> Result<Vec<u8>, Status> {
+    let body = data.open(limits.get(limit).unwrap_or_else(|| default.into())).into_bytes().await
+        .map_err(|_| Status::BadRequest)?;
+    if !body.is_complete() {
+        return Err(Status::PayloadTooLarge)
+    }
+    Ok(body.into_inner())
+}
+
+/// Tolerance, in nautical miles, of the simplification asked for with `?simplify=`, which must be
+/// positive.
+fn tolerance(simplify: form::Result<'_, f64>) -> Result<Option<f64>, Status> {
+    match optional(simplify)? {
+        Some(tolerance) if !tolerance.is_finite() || tolerance <= 0.0 => Err(Status::BadRequest),
+        tolerance => Ok(tolerance),
+    }
 }
 
-#[get("/races?<archived>")]
-async fn list(race_service: &State<RaceService>, archived: Option<bool>) -> Result<Json<Vec<Race>>, Status> {
+/// Races, optionally only those whose start, waypoints or course go through the bounding box
+/// `bbox`, or within `radiusNm` nautical miles of `near`.
+#[get("/races?<archived>&<area..>")]
+async fn list(race_service: Namespaced<'_>, archived: Option<bool>, area: AreaQuery<'_>) -> Result<Json<Vec<Race>>, Rejection> {
+
+    let bbox = optional(area.bbox)?;
+    let near = optional(area.near)?;
+    let radius = optional(area.radius_nm)?;
+    let area = match (bbox, near, radius) {
+        (None, None, None) => None,
+        (Some(bbox), None, None) => Some(Area::Box(bbox.into())),
+        (None, Some(near), Some(radius)) if radius.is_finite() && radius >= 0.0 => Some(Area::Circle { center: near.into(), radius }),
+        _ => return Err(Status::BadRequest.into()),
+    };
 
-    match race_service.list(archived).await {
+    let races = match &area {
+        Some(area) => race_service.search(archived, area).await,
+        None => race_service.list(archived).await,
+    };
+    match races {
         Ok(races) => Ok(Json(races.into_iter().map(|r| r.into()).collect())),
-        Err(_) => Err(Status::InternalServerError)
+        Err(error) => Err(Rejection::unexpected(&error))
     }
 }
 
-#[get("/races/<race_id>")]
-async fn get(race_service: &State<RaceService>, race_id: String) -> Result<Json<Race>, Status> {
+/// Race, its ice limits and zones to avoid simplified to `simplify` nautical miles if asked.
+#[get("/races/<race_id>?<simplify>", rank = 2)]
+async fn get(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, simplify: form::Result<'_, f64>) -> Result<Tagged<Json<Race>>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+    let tolerance = tolerance(simplify)?;
 
     match race_service.get(race_id).await {
-        Ok(None) => Err(Status::NotFound),
-        Ok(Some(race)) => Ok(Json(race.into())),
-        Err(_) => Err(Status::InternalServerError)
+        Ok(None) => Err(Status::NotFound.into()),
+        Ok(Some(mut race)) => {
+            let revision = race.revision().map_err(|_| Status::InternalServerError)?;
+            if let Some(tolerance) = tolerance {
+                simplify::simplify(&mut race, tolerance);
+            }
+            Ok(Tagged(Json(race.into()), revision))
+        },
+        Err(error) => Err(Rejection::unexpected(&error))
     }
 }
 
 #[post("/races", data = "<race>")]
-async fn post(race_service: &State<RaceService>, race: Json<Race>) -> Status {
+async fn post(race_service: Namespaced<'_>, race: Json<Race>, author: Author) -> Result<Status, Rejection> {
+
+    let race: race::Race = race.into_inner().try_into().map_err(Rejection::bad_request)?;
 
-    match race_service.create(&race.into_inner().into()).await {
-        Ok(_) => Status::Created,
+    match race_service.create(&race, author.0).await {
+        Ok(_) => Ok(Status::Created),
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::AlreadyExists(_)) => Status::Conflict,
-                Some(RaceError::IdIsMandatory()) => Status::BadRequest,
-                _ => Status::InternalServerError,
+                Some(RaceError::Invalid(violations)) => Err(Rejection::Invalid(violations.clone())),
+                Some(RaceError::AlreadyExists(_)) => Err(Status::Conflict.into()),
+                Some(error @ RaceError::InTrash(_)) => Err(Rejection::Error(Status::Conflict, error.to_string())),
+                Some(RaceError::IdIsMandatory()) => Err(Status::BadRequest.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
 
 #[post("/races/<race_id>/archive")]
-async fn archive(race_service: &State<RaceService>, race_id: String) -> Status {
-    match race_service.archive(race_id).await {
-        Ok(_) => Status::Ok,
+async fn archive(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, if_match: IfMatch, author: Author) -> Result<Status, Rejection> {
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.archive(race_id, if_match.revisions(), author.0).await {
+        Ok(_) => Ok(Status::Ok),
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::NotFound(_)) => Status::NotFound,
-                _ => Status::InternalServerError,
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionMismatch(_)) => Err(Status::PreconditionFailed.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
 
 #[post("/races/<race_id>/restore")]
-async fn restore(race_service: &State<RaceService>, race_id: String) -> Status {
-    match race_service.restore(race_id).await {
-        Ok(_) => Status::Created,
+async fn restore(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, author: Author) -> Result<Status, Rejection> {
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.restore(race_id, author.0).await {
+        Ok(_) => Ok(Status::Created),
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::NotFound(_)) => Status::NotFound,
-                Some(RaceError::AlreadyExists(_)) => Status::Conflict,
-                _ => Status::InternalServerError,
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::AlreadyExists(_)) => Err(Status::Conflict.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
 
 #[put("/races/<race_id>", data = "<race>")]
-async fn put(race_service: &State<RaceService>, race_id: String, race: Json<Race>) -> Status {
+async fn put(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, race: Json<Race>, if_match: IfMatch, author: Author) -> Result<Tagged<Status>, Rejection> {
 
-    match race_service.update(race_id, &race.into_inner().into()).await {
-        Ok(_) => Status::NoContent,
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    let race: race::Race = race.into_inner().try_into().map_err(Rejection::bad_request)?;
+
+    match race_service.update(race_id, &race, if_match.revisions(), author.0).await {
+        Ok(race) => {
+            let revision = race.revision().map_err(|_| Status::InternalServerError)?;
+            Ok(Tagged(Status::NoContent, revision))
+        },
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::NotFound(_)) => Status::NotFound,
-                _ => Status::InternalServerError,
+                Some(RaceError::Invalid(violations)) => Err(Rejection::Invalid(violations.clone())),
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::AlreadyExists(_)) => Err(Status::Conflict.into()),
+                Some(error @ RaceError::InTrash(_)) => Err(Rejection::Error(Status::Conflict, error.to_string())),
+                Some(RaceError::RevisionMismatch(_)) => Err(Status::PreconditionFailed.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
 
 #[delete("/races/<race_id>")]
-async fn delete(race_service: &State<RaceService>, race_id: String) -> Status {
+async fn delete(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, if_match: IfMatch, author: Author) -> Result<Status, Rejection> {
 
-    match race_service.delete(race_id).await {
-        Ok(_) => Status::NoContent,
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.delete(race_id, if_match.revisions(), author.0).await {
+        Ok(_) => Ok(Status::NoContent),
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::NotFound(_)) => Status::NotFound,
-                _ => Status::InternalServerError,
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionMismatch(_)) => Err(Status::PreconditionFailed.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
 
 #[post("/legs", data = "<leg>")]
-async fn post_leg(race_service: &State<RaceService>, polar_service: &State<PolarService>, leg: Json<Leg>) -> Status {
+async fn post_leg(race_service: Namespaced<'_>, polar_service: &State<PolarService>, leg: Json<Leg>, author: Author) -> Result<Status, Rejection> {
 
     let leg = leg.into_inner();
 
     let boat = polar_service.get_boat(leg.boat.polar_id).await.unwrap_or(String::from(""));
 
-    let mut race: race::Race = leg.into();
+    let mut race: race::Race = leg.try_into().map_err(Rejection::bad_request)?;
 
     race.boat = boat;
 
-    match race_service.create(&race).await {
-        Ok(_) => Status::Created,
+    match race_service.create(&race, author.0).await {
+        Ok(_) => Ok(Status::Created),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::Invalid(violations)) => Err(Rejection::Invalid(violations.clone())),
+                Some(RaceError::AlreadyExists(_)) => Err(Status::Conflict.into()),
+                Some(error @ RaceError::InTrash(_)) => Err(Rejection::Error(Status::Conflict, error.to_string())),
+                Some(RaceError::IdIsMandatory()) => Err(Status::BadRequest.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+
+#[get("/races/<race_id>/revisions")]
+async fn revisions(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>) -> Result<Json<Vec<Revision>>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.revisions(race_id).await {
+        Ok(revisions) => Ok(Json(revisions.into_iter().map(Revision::summary).collect())),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[get("/races/<race_id>/revisions/<number>")]
+async fn revision(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, number: u32) -> Result<Json<Revision>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.revision(race_id, number).await {
+        Ok(revision) => Ok(Json(revision.into())),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionNotFound(_, _)) => Err(Status::NotFound.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[get("/races/<race_id>/diff?<from>&<to>")]
+async fn diff(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, from: u32, to: u32) -> Result<Json<RaceDiff>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.diff(race_id, from, to).await {
+        Ok(diff) => Ok(Json(RaceDiff::new(from, to, diff))),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionNotFound(_, _)) => Err(Status::NotFound.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[post("/races/<race_id>/revisions/<number>/rollback")]
+async fn rollback(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, number: u32, if_match: IfMatch, author: Author) -> Result<Tagged<Status>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.rollback(race_id, number, if_match.revisions(), author.0).await {
+        Ok(race) => {
+            let revision = race.revision().map_err(|_| Status::InternalServerError)?;
+            Ok(Tagged(Status::NoContent, revision))
+        },
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::Invalid(violations)) => Err(Rejection::Invalid(violations.clone())),
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionNotFound(_, _)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionMismatch(_)) => Err(Status::PreconditionFailed.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[get("/trash")]
+async fn trash(race_service: Namespaced<'_>) -> Result<Json<Vec<TrashEntry>>, Rejection> {
+
+    match race_service.trash().await {
+        Ok(entries) => Ok(Json(entries.into_iter().map(|e| e.into()).collect())),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+#[post("/trash/<race_id>/restore")]
+async fn untrash(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, author: Author) -> Result<Status, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.untrash(race_id, author.0).await {
+        Ok(_) => Ok(Status::Created),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::AlreadyExists(_)) => Err(Status::Conflict.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[delete("/trash/<race_id>")]
+async fn purge(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>) -> Result<Status, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.purge(race_id).await {
+        Ok(_) => Ok(Status::NoContent),
         Err(error) => {
             match error.downcast_ref::<RaceError>() {
-                Some(RaceError::AlreadyExists(_)) => Status::Conflict,
-                _ => Status::InternalServerError,
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                _ => Err(Rejection::unexpected(&error)),
             }
         }
     }
 }
+
+#[delete("/trash")]
+async fn purge_trash(race_service: Namespaced<'_>) -> Status {
+
+    match race_service.purge_trash(None).await {
+        Ok(_) => Status::NoContent,
+        Err(_) => Status::InternalServerError,
+    }
+}
+
+#[get("/commits")]
+async fn commits(race_service: Namespaced<'_>) -> Result<Json<Vec<Commit>>, Rejection> {
+
+    match race_service.commits(None).await {
+        Ok(Some(commits)) => Ok(Json(commits.into_iter().map(|c| c.into()).collect())),
+        Ok(None) => Err(Status::NotImplemented.into()),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+#[get("/races/<race_id>/commits")]
+async fn race_commits(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>) -> Result<Json<Vec<Commit>>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    match race_service.commits(Some(race_id)).await {
+        Ok(Some(commits)) => Ok(Json(commits.into_iter().map(|c| c.into()).collect())),
+        Ok(None) => Err(Status::NotImplemented.into()),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+/// Default distance between the points of a course polyline, in nautical miles.
+const COURSE_STEP: f64 = 10.0;
+
+#[get("/races/<race_id>/course?<step>")]
+async fn course(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, step: form::Result<'_, f64>) -> Result<Json<Course>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    // at least a mile between points, so that the polyline stays reasonably small
+    let step = optional(step)?.unwrap_or(COURSE_STEP);
+    if !step.is_finite() || step < 1.0 {
+        return Err(Status::BadRequest.into())
+    }
+
+    match race_service.course(race_id, step).await {
+        Ok(Some(course)) => Ok(Json(course.into())),
+        Ok(None) => Err(Status::NotFound.into()),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+/// Zones to avoid that the position `from` is in, or that the segment from `from` to `to` crosses,
+/// positions being given as `lat,lon`.
+#[get("/races/<race_id>/zones?<from>&<to>")]
+async fn zones(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, from: form::Result<'_, LatLon>, to: form::Result<'_, LatLon>) -> Result<Json<Intersections>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+    let from = from.map_err(|_| Status::BadRequest)?;
+    let to = optional(to)?;
+
+    match race_service.intersections(race_id, from.into(), to.map(|to| to.into())).await {
+        Ok(Some(intersections)) => Ok(Json(intersections.into())),
+        Ok(None) => Err(Status::NotFound.into()),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+/// How the position `from`, or the segment from `from` to `to`, stands with the ice limits of a
+/// race, positions being given as `lat,lon`.
+#[get("/races/<race_id>/limits?<from>&<to>")]
+async fn limits(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, from: form::Result<'_, LatLon>, to: form::Result<'_, LatLon>) -> Result<Json<LimitsEvaluation>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+    let from = from.map_err(|_| Status::BadRequest)?;
+    let to = optional(to)?;
+
+    let path = std::iter::once(from).chain(to).map(|latlon| latlon.into()).collect();
+    match race_service.evaluate_limits(race_id, path).await {
+        Ok(Some(evaluation)) => Ok(Json(evaluation.into())),
+        Ok(None) => Err(Status::NotFound.into()),
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+/// Waypoints of a race that a timestamped track passed, in their order, and those it missed.
+#[post("/races/<race_id>/crossings", data = "<track>")]
+async fn crossings(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, track: Json<Vec<TrackPoint>>) -> Result<Json<Passage>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    let track = track.into_inner().into_iter().map(|point| point.into()).collect();
+    match race_service.passage(race_id, track).await {
+        Ok(Some(passage)) => Ok(Json(passage.into())),
+        Ok(None) => Err(Status::NotFound.into()),
+        Err(error) => match error.downcast_ref::<RaceError>() {
+            Some(RaceError::InvalidTrack(_)) => Err(Status::BadRequest.into()),
+            _ => Err(Rejection::unexpected(&error)),
+        }
+    }
+}
+
+/// Next waypoint and distance to go of a boat at `lat`, `lon`, having passed `passed` waypoints, or
+/// as many as estimated from its position.
+#[get("/races/<race_id>/progress?<lat>&<lon>&<passed>")]
+async fn progress(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, lat: form::Result<'_, f64>, lon: form::Result<'_, f64>, passed: form::Result<'_, usize>) -> Result<Json<Progress>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+    let lat = lat.map_err(|_| Status::BadRequest)?;
+    let lon = lon.map_err(|_| Status::BadRequest)?;
+    if !(-90.0..=90.0).contains(&lat) || !lon.is_finite() {
+        return Err(Status::BadRequest.into())
+    }
+    let passed = optional(passed)?;
+
+    match race_service.progress(race_id, race::LatLon { lat, lon }, passed).await {
+        Ok(Some(progress)) => Ok(Json(progress.into())),
+        Ok(None) => Err(Status::NotFound.into()),
+        Err(error) => match error.downcast_ref::<RaceError>() {
+            Some(RaceError::WaypointNotFound(..)) => Err(Status::BadRequest.into()),
+            _ => Err(Rejection::unexpected(&error)),
+        }
+    }
+}
+
+/// Race in another format than the JSON of the API.
+#[derive(Responder)]
+enum RaceDocument {
+    #[response(content_type = "application/geo+json")]
+    GeoJson(String),
+    #[response(content_type = "application/gpx+xml")]
+    Gpx(String),
+    #[response(content_type = "application/vnd.google-earth.kml+xml")]
+    Kml(String),
+    #[response(content_type = "application/vnd.google-earth.kmz")]
+    Kmz(Vec<u8>),
+}
+
+#[get("/races/<file>?<simplify>", rank = 1)]
+async fn get_file(race_service: Namespaced<'_>, file: RaceFile, simplify: form::Result<'_, f64>) -> Result<RaceDocument, Rejection> {
+
+    let tolerance = tolerance(simplify)?;
+
+    let mut race = match race_service.get(file.race_id).await {
+        Ok(Some(race)) => race,
+        Ok(None) => return Err(Status::NotFound.into()),
+        Err(error) => return Err(Rejection::unexpected(&error)),
+    };
+    if let Some(tolerance) = tolerance {
+        simplify::simplify(&mut race, tolerance);
+    }
+
+    let document = match file.format {
+        FileFormat::GeoJson => geojson::write(&race).map(RaceDocument::GeoJson),
+        FileFormat::Gpx => gpx::write(&race).map(RaceDocument::Gpx),
+        FileFormat::Kml => kml::write(&race).map(RaceDocument::Kml),
+        FileFormat::Kmz => kml::write_kmz(&race).map(RaceDocument::Kmz),
+    };
+    document.map_err(|_| Status::InternalServerError.into())
+}
+
+/// Replaces the waypoints of a race with the route, or the waypoints, of a GPX file.
+#[put("/races/<race_id>/waypoints", data = "<file>")]
+async fn put_waypoints(race_service: Namespaced<'_>, race_id: Result<RaceId, RaceError>, file: Data<'_>, limits: &Limits, if_match: IfMatch, author: Author) -> Result<Tagged<Status>, Rejection> {
+
+    let race_id = race_id.map_err(Rejection::bad_request)?;
+
+    let file = body(file, limits, WAYPOINTS_LIMIT, DEFAULT_WAYPOINTS_LIMIT).await?;
+    let waypoints = gpx::read(&file).map_err(Rejection::bad_request)?;
+
+    match race_service.update_waypoints(race_id, waypoints, if_match.revisions(), author.0).await {
+        Ok(race) => {
+            let revision = race.revision().map_err(|_| Status::InternalServerError)?;
+            Ok(Tagged(Status::NoContent, revision))
+        },
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::Invalid(violations)) => Err(Rejection::Invalid(violations.clone())),
+                Some(RaceError::NotFound(_)) => Err(Status::NotFound.into()),
+                Some(RaceError::RevisionMismatch(_)) => Err(Status::PreconditionFailed.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+/// Gzipped tar of the whole catalogue, downloaded as a file.
+#[derive(Responder)]
+#[response(content_type = "application/gzip")]
+struct Bundle(Vec<u8>, Header<'static>);
+
+#[get("/races/export")]
+async fn export(race_service: Namespaced<'_>) -> Result<Bundle, Rejection> {
+
+    match race_service.export().await {
+        Ok(bundle) => {
+            let file_name = format!("races-{}.tar.gz", chrono::Utc::now().format("%Y%m%d%H%M%S"));
+            Ok(Bundle(bundle, Header::new("Content-Disposition", format!("attachment; filename=\"{}\"", file_name))))
+        },
+        Err(error) => Err(Rejection::unexpected(&error))
+    }
+}
+
+#[post("/races/import?<policy>", data = "<bundle>")]
+async fn import(race_service: Namespaced<'_>, policy: form::Result<'_, ConflictPolicy>, bundle: Data<'_>, limits: &Limits, author: Author) -> Result<Json<Vec<ImportResult>>, Rejection> {
+
+    let policy = optional(policy)?.unwrap_or(ConflictPolicy::Skip);
+    let bundle = body(bundle, limits, BUNDLE_LIMIT, DEFAULT_BUNDLE_LIMIT).await?;
+
+    match race_service.import(bundle, policy, author.0).await {
+        Ok(results) => Ok(Json(results.into_iter().map(|r| r.into()).collect())),
+        Err(error) => {
+            match error.downcast_ref::<RaceError>() {
+                Some(RaceError::InvalidBundle(_)) => Err(Status::BadRequest.into()),
+                Some(RaceError::BundleTooLarge(_)) => Err(Status::PayloadTooLarge.into()),
+                _ => Err(Rejection::unexpected(&error)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    use crate::bundle;
+    use crate::config::{Config, ServiceConfig, StoreConfig};
+    use crate::gpx;
+    use crate::namespace::Namespaces;
+    use crate::polar::PolarService;
+    use crate::race::{LatLon, Race, RaceId, Waypoint, WaypointKind};
+
+    use super::BASE;
+
+    fn client() -> Client {
+        let config = Config { store: StoreConfig::Sqlite { path: String::from(":memory:") }, ..Config::default() };
+        let rocket = crate::api::init(&[])
+            .manage(Namespaces::new(&config).unwrap())
+            .manage(PolarService::new(ServiceConfig::default()));
+        Client::tracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn imports_a_bundle_larger_than_the_default_limit_of_rocket() {
+        let races: Vec<Race> = (0..300)
+            .map(|index| {
+                // coordinates that don't repeat, so that the bundle doesn't compress too well
+                let lat = (index as f64 * 0.7319).sin() * 60.0;
+                let lon = (index as f64 * 1.1173).cos() * 170.0;
+                Race {
+                    id: Some(RaceId::parse(&format!("race-{}", index)).unwrap()),
+                    race_id: None,
+                    archived: false,
+                    name: format!("Race {}", index),
+                    short_name: None,
+                    boat: String::from("imoca"),
+                    start_time: None,
+                    end_time: None,
+                    start: LatLon { lat, lon },
+                    waypoints: vec![Waypoint {
+                        name: String::from("end"),
+                        kind: WaypointKind::Finish,
+                        radius: Some(1),
+                        latlons: vec![LatLon { lat: lat / 2.0, lon: lon / 3.0 }],
+                        side: None,
+                        group: None,
+                        engine: None,
+                        checkpoint: None,
+                        to_avoid: None,
+                    }],
+                    ice_limits: None,
+                }
+            })
+            .collect();
+        let bundle = bundle::write(&races).unwrap();
+        assert!(bundle.len() > 8 * 1024);
+
+        let client = client();
+        let response = client.post(format!("{}/races/import", BASE)).body(bundle).dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        let results: Vec<Value> = response.into_json().unwrap();
+        assert_eq!(results.len(), 300);
+        assert!(results.iter().all(|result| result["status"] == "created"));
+    }
+
+    #[test]
+    fn rejects_an_unknown_conflict_policy() {
+        let client = client();
+        let response = client.post(format!("{}/races/import?policy=merge", BASE)).body(bundle::write(&[]).unwrap()).dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn rejects_a_course_step_that_is_not_a_positive_number() {
+        let client = client();
+        for step in ["ten", "0", "-5", "inf"] {
+            let response = client.get(format!("{}/races/test/course?step={}", BASE, step)).dispatch();
+
+            assert_eq!(response.status(), Status::BadRequest, "step {}", step);
+        }
+    }
+
+    #[test]
+    fn tells_why_a_gpx_file_is_invalid() {
+        let client = client();
+        let response = client.put(format!("{}/races/test/waypoints", BASE)).body("<gpx version=\"1.1\"></gpx>").dispatch();
+
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: Value = response.into_json().unwrap();
+        assert_eq!(error["message"], "Invalid GPX : no route nor waypoint");
+    }
+
+    #[test]
+    fn puts_waypoints_larger_than_the_default_limit_of_rocket() {
+        let client = client();
+        let mut race = Race {
+            id: Some(RaceId::parse("race-0").unwrap()),
+            race_id: None,
+            archived: false,
+            name: String::from("Race 0"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: LatLon { lat: 46.5, lon: -1.8 },
+            waypoints: vec![Waypoint {
+                name: String::from("end"),
+                kind: WaypointKind::Finish,
+                radius: Some(1),
+                latlons: vec![LatLon { lat: 46.4, lon: -1.9 }],
+                side: None,
+                group: None,
+                engine: None,
+                checkpoint: None,
+                to_avoid: None,
+            }],
+            ice_limits: None,
+        };
+        let bundle = bundle::write(std::slice::from_ref(&race)).unwrap();
+        let response = client.post(format!("{}/races/import", BASE)).body(bundle).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        race.waypoints = (1..200)
+            .map(|index| Waypoint {
+                name: index.to_string(),
+                kind: if index < 199 { WaypointKind::Buoy } else { WaypointKind::Finish },
+                radius: Some(1),
+                latlons: vec![LatLon { lat: (index as f64 * 0.7319).sin() * 30.0, lon: (index as f64 * 1.1173).cos() * 60.0 }],
+                side: None,
+                group: None,
+                engine: None,
+                checkpoint: None,
+                to_avoid: None,
+            })
+            .collect();
+        let file = gpx::write(&race).unwrap();
+        assert!(file.len() > 8 * 1024);
+
+        let response = client.put(format!("{}/races/race-0/waypoints", BASE)).body(file).dispatch();
+
+        assert_eq!(response.status(), Status::NoContent);
+        let race: Value = client.get(format!("{}/races/race-0", BASE)).dispatch().into_json().unwrap();
+        assert_eq!(race["waypoints"].as_array().unwrap().len(), 199);
+    }
+}
@@ -0,0 +1,35 @@
+use serde::Serialize;
+
+use crate::bundle;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct ImportResult {
+    pub(crate) id: String,
+    #[serde(flatten)]
+    pub(crate) status: ImportStatus,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub(crate) enum ImportStatus {
+    Created,
+    Skipped,
+    Overwritten,
+    Renamed { to: String },
+    Failed { error: String },
+}
+
+impl From<bundle::ImportResult> for ImportResult {
+    fn from(result: bundle::ImportResult) -> Self {
+        ImportResult {
+            id: result.id.into(),
+            status: match result.status {
+                bundle::ImportStatus::Created => ImportStatus::Created,
+                bundle::ImportStatus::Skipped => ImportStatus::Skipped,
+                bundle::ImportStatus::Overwritten => ImportStatus::Overwritten,
+                bundle::ImportStatus::Renamed(to) => ImportStatus::Renamed { to: to.into() },
+                bundle::ImportStatus::Failed(error) => ImportStatus::Failed { error },
+            },
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use serde::Serialize;
+
+use crate::api::v1::model::race::LatLon;
+use crate::course;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Course {
+    pub(crate) legs: Vec<Leg>,
+    pub(crate) length: f64,
+    pub(crate) polyline: Vec<LatLon>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Leg {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) from: Option<String>,
+    pub(crate) to: String,
+    pub(crate) start: LatLon,
+    pub(crate) end: LatLon,
+    pub(crate) distance: f64,
+    pub(crate) initial_bearing: f64,
+    pub(crate) final_bearing: f64,
+    pub(crate) cumulative: f64,
+}
+
+impl From<course::Course> for Course {
+    fn from(course: course::Course) -> Self {
+        Course {
+            legs: course.legs.into_iter().map(|l| l.into()).collect(),
+            length: course.length,
+            polyline: course.polyline.into_iter().map(|l| l.into()).collect(),
+        }
+    }
+}
+
+impl From<course::Leg> for Leg {
+    fn from(leg: course::Leg) -> Self {
+        Leg {
+            from: leg.from,
+            to: leg.to,
+            start: leg.start.into(),
+            end: leg.end.into(),
+            distance: leg.distance,
+            initial_bearing: leg.initial_bearing,
+            final_bearing: leg.final_bearing,
+            cumulative: leg.cumulative,
+        }
+    }
+}
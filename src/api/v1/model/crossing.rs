@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::crossing;
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct TrackPoint {
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    pub(crate) time: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Passage {
+    pub(crate) complete: bool,
+    pub(crate) crossings: Vec<Crossing>,
+    pub(crate) missed: Vec<Miss>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Crossing {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    pub(crate) time: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Miss {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+}
+
+impl From<TrackPoint> for crossing::TrackPoint {
+    fn from(point: TrackPoint) -> Self {
+        crossing::TrackPoint {
+            position: crate::race::LatLon { lat: point.lat, lon: point.lon },
+            time: point.time,
+        }
+    }
+}
+
+impl From<crossing::Passage> for Passage {
+    fn from(passage: crossing::Passage) -> Self {
+        Passage {
+            complete: passage.missed.is_empty(),
+            crossings: passage.crossings.into_iter().map(|c| c.into()).collect(),
+            missed: passage.missed.into_iter().map(|m| m.into()).collect(),
+        }
+    }
+}
+
+impl From<crossing::Crossing> for Crossing {
+    fn from(crossing: crossing::Crossing) -> Self {
+        Crossing {
+            waypoint: crossing.waypoint,
+            name: crossing.name,
+            lat: crossing.position.lat,
+            lon: crossing.position.lon,
+            time: crossing.time,
+        }
+    }
+}
+
+impl From<crossing::Miss> for Miss {
+    fn from(miss: crossing::Miss) -> Self {
+        Miss {
+            waypoint: miss.waypoint,
+            name: miss.name,
+        }
+    }
+}
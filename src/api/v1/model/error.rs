@@ -0,0 +1,7 @@
+use serde::Serialize;
+
+/// Body of an error response telling its cause.
+#[derive(Serialize, Debug)]
+pub(crate) struct Error {
+    pub(crate) message: String,
+}
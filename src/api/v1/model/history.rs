@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::api::v1::model::race::{LatLon, Race, Waypoint};
+use crate::history;
+use crate::history::Action;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Revision {
+    pub(crate) number: u32,
+    pub(crate) etag: String,
+    pub(crate) timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) author: Option<String>,
+    pub(crate) action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) race: Option<Race>,
+}
+
+impl Revision {
+    /// Revision metadata only, without the race content.
+    pub(crate) fn summary(revision: history::Revision) -> Self {
+        let mut revision: Revision = revision.into();
+        revision.race = None;
+        revision
+    }
+}
+
+impl From<history::Revision> for Revision {
+    fn from(revision: history::Revision) -> Self {
+        Revision {
+            number: revision.number,
+            etag: revision.race.revision().unwrap_or_default(),
+            timestamp: revision.timestamp,
+            author: revision.author,
+            action: revision.action,
+            race: Some(revision.race.into()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Commit {
+    pub(crate) id: String,
+    pub(crate) message: String,
+    pub(crate) author: String,
+    pub(crate) email: String,
+    pub(crate) timestamp: DateTime<Utc>,
+}
+
+impl From<history::Commit> for Commit {
+    fn from(commit: history::Commit) -> Self {
+        Commit {
+            id: commit.id,
+            message: commit.message,
+            author: commit.author,
+            email: commit.email,
+            timestamp: commit.timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct RaceDiff {
+    pub(crate) from: u32,
+    pub(crate) to: u32,
+    pub(crate) fields: Vec<FieldChange>,
+    pub(crate) waypoints: Vec<WaypointChange>,
+    pub(crate) ice_limits: Vec<FieldChange>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct FieldChange {
+    pub(crate) field: String,
+    pub(crate) from: Value,
+    pub(crate) to: Value,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "change", rename_all = "lowercase")]
+pub(crate) enum WaypointChange {
+    Added { index: usize, waypoint: Waypoint },
+    Removed { index: usize, waypoint: Waypoint },
+    Moved { from_index: usize, to_index: usize, name: String, from: Vec<LatLon>, to: Vec<LatLon> },
+    Modified { from_index: usize, to_index: usize, name: String, changes: Vec<FieldChange> },
+}
+
+impl RaceDiff {
+    pub(crate) fn new(from: u32, to: u32, diff: history::RaceDiff) -> Self {
+        RaceDiff {
+            from,
+            to,
+            fields: diff.fields.into_iter().map(|c| c.into()).collect(),
+            waypoints: diff.waypoints.into_iter().map(|c| c.into()).collect(),
+            ice_limits: diff.ice_limits.into_iter().map(|c| c.into()).collect(),
+        }
+    }
+}
+
+impl From<history::FieldChange> for FieldChange {
+    fn from(change: history::FieldChange) -> Self {
+        FieldChange {
+            field: change.field,
+            from: change.from,
+            to: change.to,
+        }
+    }
+}
+
+impl From<history::WaypointChange> for WaypointChange {
+    fn from(change: history::WaypointChange) -> Self {
+        match change {
+            history::WaypointChange::Added { index, waypoint } => WaypointChange::Added { index, waypoint: waypoint.into() },
+            history::WaypointChange::Removed { index, waypoint } => WaypointChange::Removed { index, waypoint: waypoint.into() },
+            history::WaypointChange::Moved { from_index, to_index, name, from, to } => WaypointChange::Moved {
+                from_index,
+                to_index,
+                name,
+                from: from.into_iter().map(|l| l.into()).collect(),
+                to: to.into_iter().map(|l| l.into()).collect(),
+            },
+            history::WaypointChange::Modified { from_index, to_index, name, changes } => WaypointChange::Modified {
+                from_index,
+                to_index,
+                name,
+                changes: changes.into_iter().map(|c| c.into()).collect(),
+            },
+        }
+    }
+}
@@ -4,6 +4,7 @@ use chrono::{DateTime, SubsecRound, Utc};
 
 use serde::{Deserialize, Serialize};
 use crate::race;
+use crate::race::RaceError;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct Leg {
@@ -233,25 +234,31 @@ impl Leg {
     }
 }
 
-impl Into<race::Race> for Leg {
+/// A leg whose name makes an invalid race id is rejected, rather than created without an id.
+impl TryFrom<Leg> for race::Race {
+    type Error = RaceError;
 
-    fn into(self) -> race::Race {
+    fn try_from(leg: Leg) -> Result<Self, Self::Error> {
 
-        let mut name = self.race.name.clone();
+        let id = race::RaceId::parse(&leg.clean_name())?;
 
-        self.id.num.map(|num| { name = format!("{} #{}", name, num); });
+        let mut name = leg.race.name.clone();
+
+        if let Some(num) = leg.id.num {
+            name = format!("{} #{}", name, num);
+        }
 
         let mut race = race::Race {
-            id: Some(self.clean_name()),
-            race_id: Some(self.id.into()),
+            id: Some(id),
+            race_id: Some(leg.id.into()),
             archived: false,
             name,
-            short_name: Some(self.race.name),
+            short_name: Some(leg.race.name),
             boat: "".to_string(),
-            start_time: Some(self.start.date.round_subsecs(0)),
-            end_time: Some(self.end.date.round_subsecs(0)),
-            start: self.start.into(),
-            waypoints: self.checkpoints.iter()
+            start_time: Some(leg.start.date.round_subsecs(0)),
+            end_time: Some(leg.end.date.round_subsecs(0)),
+            start: leg.start.into(),
+            waypoints: leg.checkpoints.iter()
                 .filter(|c| c.display != Display::None)
                 .enumerate()
                 .map(|(index, checkpoint)| {
@@ -269,27 +276,46 @@ impl Into<race::Race> for Leg {
 
                     race::Waypoint {
                         name: (index + 1).to_string(),
+                        kind: match checkpoint.display {
+                            Display::Buoy => race::WaypointKind::Buoy,
+                            _ => race::WaypointKind::Gate,
+                        },
                         radius: None,
                         latlons: latlons,
+                        side: Some(match checkpoint.side {
+                            Side::Stbd => race::Side::Stbd,
+                            Side::Port => race::Side::Port,
+                        }),
+                        group: Some(checkpoint.group),
+                        engine: Some(checkpoint.engine),
+                        checkpoint: Some(race::Checkpoint {
+                            id: checkpoint.id,
+                            name: checkpoint.name.clone(),
+                        }),
                         to_avoid: None
                     }
                 })
                 .collect(),
             ice_limits: Some(race::Limits {
-                north: self.ice_limits.north.iter().map(|latlon| latlon.clone().into()).collect(),
-                south: self.ice_limits.south.iter().map(|latlon| latlon.clone().into()).collect(),
-                max_lat: self.ice_limits.max_lat,
-                min_lat: self.ice_limits.min_lat
+                north: leg.ice_limits.north.iter().map(|latlon| latlon.clone().into()).collect(),
+                south: leg.ice_limits.south.iter().map(|latlon| latlon.clone().into()).collect(),
+                max_lat: leg.ice_limits.max_lat,
+                min_lat: leg.ice_limits.min_lat
             })
         };
 
         race.waypoints.push(race::Waypoint {
             name: "end".to_string(),
-            radius: Some(self.end.radius),
-            latlons: vec![self.end.into()],
+            kind: race::WaypointKind::Finish,
+            radius: Some(leg.end.radius),
+            latlons: vec![leg.end.into()],
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
             to_avoid: None
         });
 
-        race
+        Ok(race)
     }
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use serde::Serialize;
+
+use crate::api::v1::model::race::LatLon;
+use crate::limits;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Limit {
+    North,
+    South,
+    MaxLat,
+    MinLat,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct LimitsEvaluation {
+    pub(crate) respects: bool,
+    pub(crate) positions: Vec<Evaluation>,
+    pub(crate) crossed: Vec<Limit>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Evaluation {
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    pub(crate) respects: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) north: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) south: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) nearest: Option<Nearest>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Nearest {
+    pub(crate) limit: Limit,
+    pub(crate) distance: f64,
+}
+
+impl From<limits::PathEvaluation> for LimitsEvaluation {
+    fn from(evaluation: limits::PathEvaluation) -> Self {
+        LimitsEvaluation {
+            respects: evaluation.respects,
+            positions: evaluation.positions.into_iter().map(|e| e.into()).collect(),
+            crossed: evaluation.crossed.into_iter().map(|l| l.into()).collect(),
+        }
+    }
+}
+
+impl From<limits::Evaluation> for Evaluation {
+    fn from(evaluation: limits::Evaluation) -> Self {
+        let LatLon { lat, lon } = evaluation.position.into();
+        Evaluation {
+            lat,
+            lon,
+            respects: evaluation.respects,
+            north: evaluation.north,
+            south: evaluation.south,
+            nearest: evaluation.nearest.map(|(limit, distance)| Nearest { limit: limit.into(), distance }),
+        }
+    }
+}
+
+impl From<limits::Limit> for Limit {
+    fn from(limit: limits::Limit) -> Self {
+        match limit {
+            limits::Limit::North => Limit::North,
+            limits::Limit::South => Limit::South,
+            limits::Limit::MaxLat => Limit::MaxLat,
+            limits::Limit::MinLat => Limit::MinLat,
+        }
+    }
+}
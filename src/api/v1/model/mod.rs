@@ -1,3 +1,13 @@
+pub(crate) mod bundle;
+pub(crate) mod course;
+pub(crate) mod crossing;
+pub(crate) mod error;
+pub(crate) mod history;
 pub(crate) mod leg;
+pub(crate) mod limits;
+pub(crate) mod progress;
 pub(crate) mod race;
-
+pub(crate) mod spatial;
+pub(crate) mod trash;
+pub(crate) mod validation;
+pub(crate) mod zone;
@@ -0,0 +1,45 @@
+use serde::Serialize;
+
+use crate::api::v1::model::race::LatLon;
+use crate::progress;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Progress {
+    pub(crate) passed: usize,
+    pub(crate) estimated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) next: Option<Next>,
+    pub(crate) distance_to_go: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Next {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    pub(crate) mark: LatLon,
+    pub(crate) distance: f64,
+    pub(crate) bearing: f64,
+}
+
+impl From<progress::Progress> for Progress {
+    fn from(progress: progress::Progress) -> Self {
+        Progress {
+            passed: progress.passed,
+            estimated: progress.estimated,
+            next: progress.next.map(|n| n.into()),
+            distance_to_go: progress.distance_to_go,
+        }
+    }
+}
+
+impl From<progress::Next> for Next {
+    fn from(next: progress::Next) -> Self {
+        Next {
+            waypoint: next.waypoint,
+            name: next.name,
+            mark: next.mark.into(),
+            distance: next.distance,
+            bearing: next.bearing,
+        }
+    }
+}
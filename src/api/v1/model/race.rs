@@ -1,7 +1,10 @@
 use chrono::{DateTime, Utc};
+use rocket::form;
+use rocket::form::{FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 
 use crate::race;
+use crate::race::{RaceError, RaceId};
 
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct Race {
@@ -43,17 +46,62 @@ pub(crate) struct Limits {
 #[derive(Deserialize, Serialize, Debug)]
 pub(crate) struct Waypoint {
     pub(crate) name: String,
+    /// inferred from the radius when missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) kind: Option<WaypointKind>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) radius: Option<u8>,
     pub(crate) latlons: Vec<LatLon>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) side: Option<Side>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) group: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) engine: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) checkpoint: Option<Checkpoint>,
     #[serde(rename = "toAvoid", skip_serializing_if = "Option::is_none")]
-    pub(crate) to_avoid: Option<Vec<Vec<Vec<f64>>>>
+    pub(crate) to_avoid: Option<Vec<Polygon>>
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum WaypointKind {
+    Gate,
+    Buoy,
+    Finish,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Side {
+    Port,
+    Stbd,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct Checkpoint {
+    pub(crate) id: u8,
+    pub(crate) name: String,
+}
+
+/// Zone to avoid, a polygon with holes, also accepted as the ring of `[lat, lon]` arrays of the
+/// first versions of the API.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum Polygon {
+    Polygon {
+        exterior: Vec<LatLon>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        holes: Vec<Vec<LatLon>>,
+    },
+    Ring(Vec<[f64; 2]>),
 }
 
 impl From<race::Race> for Race {
     fn from(race: race::Race) -> Self {
         Race {
-            id: race.id.expect("Race id is not null"),
+            id: race.id.expect("Race id is not null").into(),
             race_id: race.race_id,
             archived: race.archived,
             name: race.name,
@@ -68,21 +116,23 @@ impl From<race::Race> for Race {
     }
 }
 
-impl Into<race::Race> for Race {
-    fn into(self) -> race::Race {
-        race::Race {
-            id: Some(self.id),
-            race_id: self.race_id,
-            archived: self.archived,
-            name: self.name,
-            short_name: self.short_name,
-            boat: self.boat,
-            start_time: self.start_time,
-            end_time: self.end_time,
-            start: self.start.into(),
-            waypoints: self.waypoints.into_iter().map(|w| w.into()).collect(),
-            ice_limits: self.ice_limits.map(|x| x.into())
-        }
+impl TryFrom<Race> for race::Race {
+    type Error = RaceError;
+
+    fn try_from(race: Race) -> Result<Self, Self::Error> {
+        Ok(race::Race {
+            id: Some(RaceId::parse(&race.id)?),
+            race_id: race.race_id,
+            archived: race.archived,
+            name: race.name,
+            short_name: race.short_name,
+            boat: race.boat,
+            start_time: race.start_time,
+            end_time: race.end_time,
+            start: race.start.into(),
+            waypoints: waypoints(race.waypoints),
+            ice_limits: race.ice_limits.map(|x| x.into())
+        })
     }
 }
 
@@ -131,20 +181,111 @@ impl From<race::Waypoint> for Waypoint {
     fn from(waypoint: race::Waypoint) -> Self {
         Waypoint {
             name: waypoint.name,
+            kind: Some(waypoint.kind.into()),
             radius: waypoint.radius,
             latlons: waypoint.latlons.into_iter().map(|l| l.into()).collect(),
-            to_avoid: waypoint.to_avoid
+            side: waypoint.side.map(|s| s.into()),
+            group: waypoint.group,
+            engine: waypoint.engine,
+            checkpoint: waypoint.checkpoint.map(|c| Checkpoint { id: c.id, name: c.name }),
+            to_avoid: waypoint.to_avoid.map(|zones| zones.0.into_iter().map(|p| p.into()).collect())
         }
     }
 }
 
-impl Into<race::Waypoint> for Waypoint {
-    fn into(self) -> race::Waypoint {
-        race::Waypoint {
-            name: self.name,
-            radius: self.radius,
-            latlons: self.latlons.into_iter().map(|l| l.into()).collect(),
-            to_avoid: self.to_avoid
+/// Converts the waypoints of a race, the kind of those without one being inferred from their
+/// radius and place.
+fn waypoints(waypoints: Vec<Waypoint>) -> Vec<race::Waypoint> {
+    let count = waypoints.len();
+    waypoints.into_iter()
+        .enumerate()
+        .map(|(index, waypoint)| race::Waypoint {
+            kind: waypoint.kind.map_or_else(|| race::WaypointKind::infer(waypoint.radius, index + 1 == count), |k| k.into()),
+            name: waypoint.name,
+            radius: waypoint.radius,
+            latlons: waypoint.latlons.into_iter().map(|l| l.into()).collect(),
+            side: waypoint.side.map(|s| s.into()),
+            group: waypoint.group,
+            engine: waypoint.engine,
+            checkpoint: waypoint.checkpoint.map(|c| race::Checkpoint { id: c.id, name: c.name }),
+            to_avoid: waypoint.to_avoid.map(|zones| race::MultiPolygon(zones.into_iter().map(|p| p.into()).collect()))
+        })
+        .collect()
+}
+
+impl From<race::WaypointKind> for WaypointKind {
+    fn from(kind: race::WaypointKind) -> Self {
+        match kind {
+            race::WaypointKind::Gate => WaypointKind::Gate,
+            race::WaypointKind::Buoy => WaypointKind::Buoy,
+            race::WaypointKind::Finish => WaypointKind::Finish,
+        }
+    }
+}
+
+impl From<WaypointKind> for race::WaypointKind {
+    fn from(kind: WaypointKind) -> Self {
+        match kind {
+            WaypointKind::Gate => race::WaypointKind::Gate,
+            WaypointKind::Buoy => race::WaypointKind::Buoy,
+            WaypointKind::Finish => race::WaypointKind::Finish,
+        }
+    }
+}
+
+impl From<race::Side> for Side {
+    fn from(side: race::Side) -> Self {
+        match side {
+            race::Side::Port => Side::Port,
+            race::Side::Stbd => Side::Stbd,
+        }
+    }
+}
+
+impl From<Side> for race::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Port => race::Side::Port,
+            Side::Stbd => race::Side::Stbd,
+        }
+    }
+}
+
+impl From<race::Polygon> for Polygon {
+    fn from(polygon: race::Polygon) -> Self {
+        Polygon::Polygon {
+            exterior: polygon.exterior.into_iter().map(|l| l.into()).collect(),
+            holes: polygon.holes.into_iter().map(|h| h.into_iter().map(|l| l.into()).collect()).collect(),
+        }
+    }
+}
+
+impl From<Polygon> for race::Polygon {
+    fn from(polygon: Polygon) -> Self {
+        match polygon {
+            Polygon::Polygon { exterior, holes } => race::Polygon {
+                exterior: exterior.into_iter().map(|l| l.into()).collect(),
+                holes: holes.into_iter().map(|h| h.into_iter().map(|l| l.into()).collect()).collect(),
+            },
+            Polygon::Ring(ring) => race::Polygon {
+                exterior: ring.into_iter().map(|[lat, lon]| race::LatLon { lat, lon }).collect(),
+                holes: Vec::new(),
+            },
+        }
+    }
+}
+
+/// A position given as `lat,lon` in a query.
+impl<'v> FromFormField<'v> for LatLon {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let coordinate = |value: Option<&str>| value
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite());
+
+        let mut values = field.value.split(',');
+        match (coordinate(values.next()), coordinate(values.next()), values.next()) {
+            (Some(lat), Some(lon), None) => Ok(LatLon { lat, lon }),
+            _ => Err(form::Error::validation("expected lat,lon").into()),
         }
     }
 }
@@ -0,0 +1,50 @@
+use rocket::form;
+use rocket::form::{FromForm, FromFormField, ValueField};
+
+use crate::api::v1::model::race::LatLon;
+use crate::spatial;
+
+/// `minLon,minLat,maxLon,maxLat` bounding box, across the antimeridian when `minLon` is greater
+/// than `maxLon`.
+#[derive(Debug)]
+pub(crate) struct BoundingBox {
+    pub(crate) min_lon: f64,
+    pub(crate) min_lat: f64,
+    pub(crate) max_lon: f64,
+    pub(crate) max_lat: f64,
+}
+
+/// Area races are searched in, either `bbox`, or `near` with `radiusNm`.
+#[derive(FromForm, Debug)]
+pub(crate) struct AreaQuery<'v> {
+    pub(crate) bbox: form::Result<'v, BoundingBox>,
+    pub(crate) near: form::Result<'v, LatLon>,
+    #[field(name = "radiusNm")]
+    pub(crate) radius_nm: form::Result<'v, f64>,
+}
+
+impl<'v> FromFormField<'v> for BoundingBox {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let invalid = || form::Error::validation("expected minLon,minLat,maxLon,maxLat");
+        let values = field.value.split(',')
+            .map(|value| value.trim().parse::<f64>().ok().filter(|value| value.is_finite()))
+            .collect::<Option<Vec<f64>>>()
+            .ok_or_else(invalid)?;
+        match values.as_slice() {
+            &[min_lon, min_lat, max_lon, max_lat] if -90.0 <= min_lat && min_lat <= max_lat && max_lat <= 90.0 =>
+                Ok(BoundingBox { min_lon, min_lat, max_lon, max_lat }),
+            _ => Err(invalid().into()),
+        }
+    }
+}
+
+impl From<BoundingBox> for spatial::BoundingBox {
+    fn from(bbox: BoundingBox) -> Self {
+        spatial::BoundingBox {
+            west: bbox.min_lon,
+            south: bbox.min_lat,
+            east: bbox.max_lon,
+            north: bbox.max_lat,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::api::v1::model::race::Race;
+use crate::trash;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct TrashEntry {
+    pub(crate) deleted_at: DateTime<Utc>,
+    pub(crate) race: Race,
+}
+
+impl From<trash::TrashEntry> for TrashEntry {
+    fn from(entry: trash::TrashEntry) -> Self {
+        TrashEntry {
+            deleted_at: entry.deleted_at,
+            race: entry.race.into(),
+        }
+    }
+}
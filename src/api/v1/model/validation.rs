@@ -0,0 +1,23 @@
+use serde::Serialize;
+
+use crate::validation;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Violations {
+    pub(crate) violations: Vec<Violation>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Violation {
+    pub(crate) path: String,
+    pub(crate) message: String,
+}
+
+impl From<validation::Violation> for Violation {
+    fn from(violation: validation::Violation) -> Self {
+        Violation {
+            path: violation.path,
+            message: violation.message,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use serde::Serialize;
+
+use crate::zone;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Intersections {
+    pub(crate) intersects: bool,
+    pub(crate) zones: Vec<Intersection>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Intersection {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    pub(crate) index: usize,
+}
+
+impl From<Vec<zone::Intersection>> for Intersections {
+    fn from(intersections: Vec<zone::Intersection>) -> Self {
+        Intersections {
+            intersects: !intersections.is_empty(),
+            zones: intersections.into_iter().map(|i| i.into()).collect(),
+        }
+    }
+}
+
+impl From<zone::Intersection> for Intersection {
+    fn from(intersection: zone::Intersection) -> Self {
+        Intersection {
+            waypoint: intersection.waypoint,
+            name: intersection.name,
+            index: intersection.index,
+        }
+    }
+}
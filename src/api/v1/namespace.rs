@@ -0,0 +1,43 @@
+use std::ops::Deref;
+
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::api::v1::BASE;
+use crate::namespace::Namespaces;
+use crate::race::RaceService;
+
+/// Race service of the namespace the matched route is mounted for, the default one when mounted
+/// at the root of the API.
+pub(crate) struct Namespaced<'r>(&'r RaceService);
+
+impl Deref for Namespaced<'_> {
+    type Target = RaceService;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Namespaced<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let namespaces = match request.rocket().state::<Namespaces>() {
+            Some(namespaces) => namespaces,
+            None => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+
+        let namespace = request.route()
+            .and_then(|route| route.uri.base().strip_prefix(BASE))
+            .map(|namespace| namespace.trim_matches('/'))
+            .filter(|namespace| !namespace.is_empty());
+
+        match namespaces.get(namespace) {
+            Some(race_service) => Outcome::Success(Namespaced(race_service)),
+            None => Outcome::Failure((Status::NotFound, ())),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket::Request;
+
+use crate::api::v1::model::error::Error;
+use crate::api::v1::model::validation::Violations;
+use crate::race::RaceError;
+use crate::validation::Violation;
+
+/// Error response: a bare status, 422 with the violations of an invalid race, or a status with a
+/// message telling the client what went wrong.
+pub(crate) enum Rejection {
+    Status(Status),
+    Invalid(Vec<Violation>),
+    Error(Status, String),
+}
+
+impl Rejection {
+    /// 400 telling why the request is invalid, such as the grammar of race ids it broke.
+    pub(crate) fn bad_request(error: RaceError) -> Self {
+        Rejection::Error(Status::BadRequest, error.to_string())
+    }
+
+    /// Response to an error a route doesn't handle itself: 409 for a race stored in several files,
+    /// which must be fixed on disk, 500 for anything else.
+    pub(crate) fn unexpected(error: &anyhow::Error) -> Self {
+        match error.downcast_ref::<RaceError>() {
+            Some(error @ RaceError::DuplicateFiles(..)) => Rejection::Error(Status::Conflict, error.to_string()),
+            _ => Rejection::Status(Status::InternalServerError),
+        }
+    }
+}
+
+impl From<Status> for Rejection {
+    fn from(status: Status) -> Self {
+        Rejection::Status(status)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Rejection {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        match self {
+            Rejection::Status(status) => status.respond_to(request),
+            Rejection::Invalid(violations) => {
+                let violations = Violations { violations: violations.into_iter().map(|v| v.into()).collect() };
+                (Status::UnprocessableEntity, Json(violations)).respond_to(request)
+            },
+            Rejection::Error(status, message) => (status, Json(Error { message })).respond_to(request),
+        }
+    }
+}
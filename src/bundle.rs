@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+
+use crate::race::{Race, RaceError, RaceId};
+use crate::schema;
+use crate::schema::Versioned;
+
+const MANIFEST: &str = "manifest.yaml";
+const VERSION: u32 = 1;
+/// Largest size of a file of a bundle, once decompressed.
+const MAX_FILE_SIZE: u64 = 16 << 20;
+/// Largest size of all the files of a bundle, once decompressed.
+const MAX_SIZE: u64 = 256 << 20;
+
+/// Table of contents of a bundle, listing every race it contains.
+#[derive(Deserialize, Serialize, Debug)]
+struct Manifest {
+    version: u32,
+    exported_at: DateTime<Utc>,
+    races: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct ManifestEntry {
+    id: RaceId,
+    #[serde(default)]
+    archived: bool,
+    name: String,
+    /// path of the race document inside the bundle
+    file: String,
+}
+
+/// What to do when an imported race has the id of an existing one.
+#[derive(FromFormField, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ConflictPolicy {
+    /// keep the existing race
+    Skip,
+    /// replace the existing race
+    Overwrite,
+    /// import the race under a free id
+    Rename,
+}
+
+/// Outcome of the import of one race of a bundle.
+#[derive(Debug)]
+pub(crate) struct ImportResult {
+    pub(crate) id: RaceId,
+    pub(crate) status: ImportStatus,
+}
+
+#[derive(Debug)]
+pub(crate) enum ImportStatus {
+    Created,
+    Skipped,
+    Overwritten,
+    Renamed(RaceId),
+    Failed(String),
+}
+
+/// Writes the races, active and archived, as a gzipped tar with a manifest.
+pub(crate) fn write(races: &[Race]) -> Result<Vec<u8>> {
+    let now = Utc::now();
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    let mut entries = Vec::new();
+    for race in races {
+        let id = match &race.id {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        let file = format!("{}/{}.yaml", if race.archived { "archived" } else { "races" }, id);
+        append(&mut builder, &file, &serde_yaml::to_vec(&Versioned::new(race))?, now)?;
+        entries.push(ManifestEntry { id, archived: race.archived, name: race.name.clone(), file });
+    }
+
+    let manifest = Manifest { version: VERSION, exported_at: now, races: entries };
+    append(&mut builder, MANIFEST, &serde_yaml::to_vec(&manifest)?, now)?;
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn append(builder: &mut tar::Builder<GzEncoder<Vec<u8>>>, path: &str, content: &[u8], mtime: DateTime<Utc>) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, path, content)?;
+    Ok(())
+}
+
+/// Reads the races listed in the manifest of a bundle, in order. Races which can't be read are
+/// returned as errors, so that the others can still be imported. Bundles whose files are too large
+/// once decompressed are rejected before they are read whole.
+pub(crate) fn read(bundle: &[u8]) -> Result<Vec<(RaceId, Result<Race>)>> {
+    let invalid = |e: &dyn std::fmt::Display| RaceError::InvalidBundle(e.to_string());
+
+    let mut files = HashMap::new();
+    let mut size = 0;
+    let mut archive = tar::Archive::new(GzDecoder::new(bundle));
+    for entry in archive.entries().map_err(|e| invalid(&e))? {
+        let mut entry = entry.map_err(|e| invalid(&e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(|e| invalid(&e))?.to_string_lossy().to_string();
+        let mut content = Vec::new();
+        (&mut entry).take(MAX_FILE_SIZE + 1).read_to_end(&mut content).map_err(|e| invalid(&e))?;
+        if content.len() as u64 > MAX_FILE_SIZE {
+            return Err(RaceError::BundleTooLarge(format!("{} is larger than {} bytes", path, MAX_FILE_SIZE)).into())
+        }
+        size += content.len() as u64;
+        if size > MAX_SIZE {
+            return Err(RaceError::BundleTooLarge(format!("its files are larger than {} bytes", MAX_SIZE)).into())
+        }
+        files.insert(path, content);
+    }
+
+    let manifest = files.get(MANIFEST).ok_or_else(|| invalid(&"missing manifest"))?;
+    let manifest: Manifest = serde_yaml::from_slice(manifest).map_err(|e| invalid(&e))?;
+    if manifest.version > VERSION {
+        return Err(invalid(&format!("unsupported version {}", manifest.version)).into())
+    }
+
+    Ok(manifest.races.into_iter()
+        .map(|entry| {
+            let race = match files.get(&entry.file) {
+                Some(content) => serde_yaml::from_slice(content)
+                    .map_err(|e| e.into())
+                    .and_then(schema::upgrade)
+                    .map(|mut race| {
+                        race.id = Some(entry.id.clone());
+                        race.archived = entry.archived;
+                        race
+                    }),
+                None => Err(anyhow::anyhow!("missing file {}", entry.file)),
+            };
+            (entry.id, race)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use crate::race::{LatLon, Race, RaceError, RaceId, Waypoint, WaypointKind};
+
+    use super::{append, read, write, MAX_FILE_SIZE};
+
+    fn race(id: &str, archived: bool) -> Race {
+        Race {
+            id: Some(RaceId::parse(id).unwrap()),
+            race_id: None,
+            archived,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: LatLon { lat: 46.5, lon: -1.8 },
+            waypoints: vec![Waypoint {
+                name: String::from("end"),
+                kind: WaypointKind::Finish,
+                radius: Some(1),
+                latlons: vec![LatLon { lat: 46.4, lon: -1.9 }],
+                side: None,
+                group: None,
+                engine: None,
+                checkpoint: None,
+                to_avoid: None,
+            }],
+            ice_limits: None,
+        }
+    }
+
+    #[test]
+    fn reads_back_the_races_it_writes() {
+        let races = [race("active", false), race("archived", true)];
+
+        let read = read(&write(&races).unwrap()).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for ((id, race), original) in read.into_iter().zip(&races) {
+            let race = race.unwrap();
+            assert_eq!(Some(&id), original.id.as_ref());
+            assert_eq!(race.id, original.id);
+            assert_eq!(race.archived, original.archived);
+            assert_eq!(race.revision().unwrap(), original.revision().unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_bundle() {
+        assert!(read(b"races").is_err());
+    }
+
+    #[test]
+    fn rejects_files_too_large_once_decompressed() {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        append(&mut builder, "races/large.yaml", &vec![b' '; MAX_FILE_SIZE as usize + 1], Utc::now()).unwrap();
+        let bundle = builder.into_inner().unwrap().finish().unwrap();
+
+        let error = read(&bundle).unwrap_err();
+
+        assert!(matches!(error.downcast_ref::<RaceError>(), Some(RaceError::BundleTooLarge(_))));
+    }
+}
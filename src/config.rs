@@ -1,14 +1,86 @@
+use std::collections::HashMap;
+
 use serde::{Serialize, Deserialize};
 
+use crate::longitudes::Longitudes;
+use crate::store::format::Format;
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct Config {
     pub(crate) races_dir: String,
     pub(crate) archived_dir: String,
+    #[serde(default)]
+    pub(crate) store: StoreConfig,
+    #[serde(default)]
+    pub(crate) trash: TrashConfig,
+    /// catalogues served under `/races/api/v1/<namespace>`, each with its own store
+    #[serde(default)]
+    pub(crate) namespaces: HashMap<String, NamespaceConfig>,
+    /// range the longitudes of races are normalized to, `signed` or `positive`
+    #[serde(default)]
+    pub(crate) longitudes: Longitudes,
     pub(crate) polars: ServiceConfig,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NamespaceConfig {
+    pub(crate) races_dir: String,
+    pub(crate) archived_dir: String,
+    #[serde(default)]
+    pub(crate) store: StoreConfig,
+}
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub(crate) struct ServiceConfig {
     pub(crate) url: String
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum StoreConfig {
+    /// One file per race in `racesDir` and `archivedDir`, new files being written in `format`
+    File {
+        #[serde(default)]
+        format: Format,
+    },
+    /// Same as `File`, with `racesDir` a git repository and every change committed
+    Git {
+        #[serde(default = "default_git_author")]
+        author: String,
+        #[serde(default = "default_git_email")]
+        email: String,
+        #[serde(default)]
+        format: Format,
+    },
+    /// Embedded SQLite database
+    Sqlite { path: String },
+}
+
+fn default_git_author() -> String {
+    String::from("races")
+}
+
+fn default_git_email() -> String {
+    String::from("races@localhost")
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig::File { format: Format::default() }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrashConfig {
+    /// deleted races are purged after this number of days
+    pub(crate) retention_days: i64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: 30 }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::geo;
+use crate::race::{LatLon, Race, Waypoint};
+
+/// Course of a race, from its start through each of its waypoints.
+#[derive(Debug)]
+pub(crate) struct Course {
+    pub(crate) legs: Vec<Leg>,
+    /// total length, in nautical miles
+    pub(crate) length: f64,
+    /// great-circle route through every leg, for drawing
+    pub(crate) polyline: Vec<LatLon>,
+}
+
+/// Great-circle leg between two consecutive marks of the course. Distances are in nautical miles
+/// and bearings in degrees.
+#[derive(Debug)]
+pub(crate) struct Leg {
+    /// name of the waypoint the leg starts from, `None` for the start
+    pub(crate) from: Option<String>,
+    pub(crate) to: String,
+    pub(crate) start: LatLon,
+    pub(crate) end: LatLon,
+    pub(crate) distance: f64,
+    pub(crate) initial_bearing: f64,
+    pub(crate) final_bearing: f64,
+    /// distance from the start of the race to the end of the leg
+    pub(crate) cumulative: f64,
+}
+
+/// Point the course goes through at a waypoint: the midpoint of a gate, the center of a circle.
+pub(crate) fn mark(waypoint: &Waypoint) -> Option<LatLon> {
+    match waypoint.latlons.as_slice() {
+        [point] => Some(point.clone()),
+        [a, b] => Some(geo::midpoint(a, b)),
+        _ => None,
+    }
+}
+
+/// Computes the legs of the course, with a polyline whose points are at most `step` nautical
+/// miles apart.
+pub(crate) fn course(race: &Race, step: f64) -> Course {
+    let mut legs: Vec<Leg> = Vec::new();
+    let mut polyline = vec![race.start.clone()];
+    let mut from = (None, race.start.clone());
+    let mut cumulative = 0.0;
+
+    for waypoint in &race.waypoints {
+        let end = match mark(waypoint) {
+            Some(end) => end,
+            None => continue,
+        };
+
+        let distance = geo::distance(&from.1, &end);
+        cumulative += distance;
+        polyline.extend(geo::densify(&from.1, &end, step).into_iter().skip(1));
+
+        legs.push(Leg {
+            from: from.0,
+            to: waypoint.name.clone(),
+            initial_bearing: geo::initial_bearing(&from.1, &end),
+            final_bearing: geo::final_bearing(&from.1, &end),
+            start: from.1,
+            end: end.clone(),
+            distance,
+            cumulative,
+        });
+        from = (Some(waypoint.name.clone()), end);
+    }
+
+    Course { legs, length: cumulative, polyline }
+}
@@ -0,0 +1,300 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::geo;
+use crate::race::{LatLon, Race, RaceError, Side};
+
+/// Position of a boat at a time.
+#[derive(Debug)]
+pub(crate) struct TrackPoint {
+    pub(crate) position: LatLon,
+    pub(crate) time: DateTime<Utc>,
+}
+
+/// Waypoint validly passed by a track, where and when it was.
+#[derive(Debug)]
+pub(crate) struct Crossing {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    pub(crate) position: LatLon,
+    pub(crate) time: DateTime<Utc>,
+}
+
+/// Waypoint a track didn't pass.
+#[derive(Debug)]
+pub(crate) struct Miss {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+}
+
+/// How a track went through the waypoints of a race, in their order.
+#[derive(Debug)]
+pub(crate) struct Passage {
+    pub(crate) crossings: Vec<Crossing>,
+    pub(crate) missed: Vec<Miss>,
+}
+
+/// Number of halvings locating where a track enters the circle of a buoy.
+const BISECTIONS: usize = 32;
+
+/// Follows a track through the waypoints of a race, each being looked for after the previous one
+/// was passed, or from the same place when it was missed. A gate is passed when the track crosses
+/// it leaving its mark on the side of the waypoint, the first point to port without one, and a buoy
+/// when the track comes within its radius. The track is straight, in the plane of longitudes and
+/// latitudes, between its points, at a constant speed.
+pub(crate) fn passage(race: &Race, track: &[TrackPoint]) -> Result<Passage> {
+    if track.len() < 2 {
+        return Err(RaceError::InvalidTrack("a track needs at least two points".to_string()).into())
+    }
+    if track.iter().any(|point| !(-90.0..=90.0).contains(&point.position.lat)) {
+        return Err(RaceError::InvalidTrack("latitudes must be between -90 and 90".to_string()).into())
+    }
+    if track.windows(2).any(|points| points[1].time < points[0].time) {
+        return Err(RaceError::InvalidTrack("the times of a track must not decrease".to_string()).into())
+    }
+
+    let mut passage = Passage { crossings: Vec::new(), missed: Vec::new() };
+    // segment of the track, and fraction of it, where the last waypoint was passed
+    let mut at = (0, 0.0);
+    for (index, waypoint) in race.waypoints.iter().enumerate() {
+        let found = match (waypoint.radius, waypoint.latlons.as_slice()) {
+            (Some(radius), [center]) => find(track, at, |from, to| reach(from, to, center, radius as f64)),
+            (None, [a, b]) => {
+                // the mark is the point of the line on its side, as `Waypoint::side` tells
+                let (mark, other, side) = match waypoint.side {
+                    Some(Side::Stbd) => (b, a, Side::Stbd),
+                    _ => (a, b, Side::Port),
+                };
+                find(track, at, |from, to| cross(from, to, mark, other, side))
+            },
+            _ => None,
+        };
+        match found {
+            Some(found) => {
+                at = found;
+                passage.crossings.push(Crossing {
+                    waypoint: index,
+                    name: waypoint.name.clone(),
+                    position: position_at(track, found),
+                    time: time_at(track, found),
+                });
+            },
+            None => passage.missed.push(Miss { waypoint: index, name: waypoint.name.clone() }),
+        }
+    }
+    Ok(passage)
+}
+
+/// First place of the track after `at` where `test` finds something on a segment, given the
+/// fraction of the segment at which it does.
+fn find<F>(track: &[TrackPoint], at: (usize, f64), test: F) -> Option<(usize, f64)>
+    where F: Fn(&LatLon, &LatLon) -> Option<f64> {
+    (at.0..track.len() - 1).find_map(|segment| {
+        let start = if segment == at.0 { at.1 } else { 0.0 };
+        let from = position_at(track, (segment, start));
+        test(&from, &track[segment + 1].position).map(|fraction| (segment, start + fraction * (1.0 - start)))
+    })
+}
+
+/// Fraction of the segment at which it crosses the gate from `mark` to `other`, leaving `mark` on
+/// `side`.
+fn cross(from: &LatLon, to: &LatLon, mark: &LatLon, other: &LatLon, side: Side) -> Option<f64> {
+    let gate = geo::unwrap(&[mark.clone(), other.clone()]);
+    shifted(from, to).iter()
+        .filter_map(|(from, to)| geo::crossing(from, to, &gate[0], &gate[1]))
+        // crossings at the very start of a segment belong to the previous one
+        .filter(|&(fraction, port)| port == (side == Side::Port) && fraction > 0.0)
+        .map(|(fraction, _)| fraction)
+        .reduce(f64::min)
+}
+
+/// Fraction of the segment at which it enters the circle of `radius` around `center`.
+fn reach(from: &LatLon, to: &LatLon, center: &LatLon, radius: f64) -> Option<f64> {
+    shifted(from, to).iter()
+        .filter_map(|(from, to)| {
+            if geo::distance(center, from) <= radius {
+                return Some(0.0)
+            }
+            let nearest = geo::nearest_fraction(center, from, to);
+            if geo::distance(center, &lerp(from, to, nearest)) > radius {
+                return None
+            }
+            let (mut outside, mut inside) = (0.0, nearest);
+            for _ in 0..BISECTIONS {
+                let middle = (outside + inside) / 2.0;
+                if geo::distance(center, &lerp(from, to, middle)) <= radius {
+                    inside = middle;
+                } else {
+                    outside = middle;
+                }
+            }
+            Some(inside)
+        })
+        .reduce(f64::min)
+}
+
+/// The segment with continuous longitudes, and its copies a turn east and west.
+fn shifted(from: &LatLon, to: &LatLon) -> Vec<(LatLon, LatLon)> {
+    let segment = geo::unwrap(&[from.clone(), to.clone()]);
+    [-360.0, 0.0, 360.0].iter()
+        .map(|shift| (
+            LatLon { lat: segment[0].lat, lon: segment[0].lon + shift },
+            LatLon { lat: segment[1].lat, lon: segment[1].lon + shift },
+        ))
+        .collect()
+}
+
+fn lerp(from: &LatLon, to: &LatLon, fraction: f64) -> LatLon {
+    LatLon { lat: from.lat + fraction * (to.lat - from.lat), lon: from.lon + fraction * (to.lon - from.lon) }
+}
+
+fn position_at(track: &[TrackPoint], (segment, fraction): (usize, f64)) -> LatLon {
+    let points = geo::unwrap(&[track[segment].position.clone(), track[segment + 1].position.clone()]);
+    let position = lerp(&points[0], &points[1], fraction);
+    LatLon { lat: position.lat, lon: geo::normalize_lon(position.lon) }
+}
+
+fn time_at(track: &[TrackPoint], (segment, fraction): (usize, f64)) -> DateTime<Utc> {
+    let elapsed = (track[segment + 1].time - track[segment].time).num_milliseconds() as f64 * fraction;
+    track[segment].time + Duration::milliseconds(elapsed.round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::race::{LatLon, Race, Side, Waypoint, WaypointKind};
+
+    use super::{passage, TrackPoint};
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    /// Gate along the 45th parallel between 4°W and 5°W, then a buoy of 5 miles off Cape Finisterre,
+    /// for tracks to cross or miss.
+    fn race() -> Race {
+        let waypoint = |name: &str, kind: WaypointKind, radius: Option<u8>, latlons: Vec<LatLon>| Waypoint {
+            name: name.to_string(),
+            kind,
+            radius,
+            latlons,
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
+            to_avoid: None,
+        };
+        Race {
+            id: None,
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: latlon(46.5, -1.8),
+            waypoints: vec![
+                waypoint("1", WaypointKind::Gate, None, vec![latlon(45.0, -4.0), latlon(45.0, -5.0)]),
+                waypoint("2", WaypointKind::Buoy, Some(5), vec![latlon(43.0, -9.5)]),
+                waypoint("end", WaypointKind::Finish, Some(1), vec![latlon(46.4, -1.9)]),
+            ],
+            ice_limits: None,
+        }
+    }
+
+    /// Track through the points, an hour apart.
+    fn track(points: &[LatLon]) -> Vec<TrackPoint> {
+        let start = Utc.ymd(2021, 11, 7).and_hms(12, 0, 0);
+        points.iter()
+            .enumerate()
+            .map(|(index, position)| TrackPoint { position: position.clone(), time: start + Duration::hours(index as i64) })
+            .collect()
+    }
+
+    fn crossed(points: &[LatLon], side: Option<Side>) -> Vec<usize> {
+        let mut race = race();
+        race.waypoints.truncate(1);
+        race.waypoints[0].side = side;
+        passage(&race, &track(points)).unwrap().crossings.iter().map(|c| c.waypoint).collect()
+    }
+
+    #[test]
+    fn passes_a_gate_leaving_its_first_point_to_port() {
+        // the gate goes from east to west, its first point is to port heading south
+        let south = [latlon(46.0, -4.5), latlon(44.0, -4.5)];
+        let north = [latlon(44.0, -4.5), latlon(46.0, -4.5)];
+
+        assert_eq!(crossed(&south, None), [0]);
+        assert!(crossed(&north, None).is_empty());
+    }
+
+    #[test]
+    fn passes_a_gate_leaving_its_mark_on_its_side() {
+        let south = [latlon(46.0, -4.5), latlon(44.0, -4.5)];
+        let north = [latlon(44.0, -4.5), latlon(46.0, -4.5)];
+
+        // the mark to port is the first point, the one to starboard the second one
+        assert_eq!(crossed(&south, Some(Side::Port)), [0]);
+        assert!(crossed(&north, Some(Side::Port)).is_empty());
+        assert_eq!(crossed(&south, Some(Side::Stbd)), [0]);
+        assert!(crossed(&north, Some(Side::Stbd)).is_empty());
+
+        // swapping the points of a starboard gate reverses it
+        let mut race = race();
+        race.waypoints.truncate(1);
+        race.waypoints[0].side = Some(Side::Stbd);
+        race.waypoints[0].latlons.reverse();
+        let crossings = |points: &[LatLon]| passage(&race, &track(points)).unwrap().crossings.len();
+        assert_eq!(crossings(&north), 1);
+        assert_eq!(crossings(&south), 0);
+    }
+
+    #[test]
+    fn finds_where_and_when_the_waypoints_are_passed_in_order() {
+        let points = [latlon(46.5, -1.8), latlon(46.0, -4.5), latlon(44.0, -4.5), latlon(43.0, -9.5), latlon(46.4, -1.9)];
+
+        let passage = passage(&race(), &track(&points)).unwrap();
+
+        assert!(passage.missed.is_empty());
+        let waypoints: Vec<usize> = passage.crossings.iter().map(|c| c.waypoint).collect();
+        assert_eq!(waypoints, [0, 1, 2]);
+        let gate = &passage.crossings[0];
+        assert!((gate.position.lat - 45.0).abs() < 1e-9);
+        assert_eq!(gate.time, track(&points)[1].time + Duration::minutes(30));
+    }
+
+    #[test]
+    fn misses_a_buoy_out_of_reach() {
+        let points = [latlon(46.5, -1.8), latlon(46.0, -4.5), latlon(44.0, -4.5), latlon(43.0, -8.0), latlon(46.4, -1.9)];
+
+        let passage = passage(&race(), &track(&points)).unwrap();
+
+        let missed: Vec<usize> = passage.missed.iter().map(|m| m.waypoint).collect();
+        assert_eq!(missed, [1]);
+        let crossed: Vec<usize> = passage.crossings.iter().map(|c| c.waypoint).collect();
+        assert_eq!(crossed, [0, 2]);
+    }
+
+    #[test]
+    fn crosses_a_gate_on_the_antimeridian() {
+        let mut race = race();
+        race.waypoints.truncate(1);
+        race.waypoints[0].latlons = vec![latlon(-50.0, -179.0), latlon(-50.0, 179.0)];
+
+        let points = [latlon(-49.0, 179.5), latlon(-51.0, -179.5)];
+
+        let passage = passage(&race, &track(&points)).unwrap();
+        assert_eq!(passage.crossings.len(), 1);
+        assert!(passage.crossings[0].position.lon.abs() == 180.0);
+    }
+
+    #[test]
+    fn rejects_tracks_going_back_in_time() {
+        let mut track = track(&[latlon(46.0, -4.5), latlon(44.0, -4.5)]);
+        track.reverse();
+
+        assert!(passage(&race(), &track).is_err());
+    }
+}
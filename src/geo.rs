@@ -0,0 +1,420 @@
+use crate::race::{LatLon, Polygon};
+
+/// Mean radius of the earth, in nautical miles.
+pub(crate) const EARTH_RADIUS: f64 = 3440.065;
+
+/// Longitude in [-180, 180).
+pub(crate) fn normalize_lon(lon: f64) -> f64 {
+    if (-180.0..180.0).contains(&lon) {
+        // untouched, rather than rounded by the arithmetic
+        return lon
+    }
+    (lon + 180.0).rem_euclid(360.0) - 180.0
+}
+
+/// Great-circle distance, in nautical miles.
+pub(crate) fn distance(from: &LatLon, to: &LatLon) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (to.lon - from.lon).to_radians();
+
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Bearing at departure of the great circle from `from` to `to`, in degrees from the north.
+pub(crate) fn initial_bearing(from: &LatLon, to: &LatLon) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let dlon = (to.lon - from.lon).to_radians();
+
+    let y = dlon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Bearing at arrival of the great circle from `from` to `to`, in degrees from the north.
+pub(crate) fn final_bearing(from: &LatLon, to: &LatLon) -> f64 {
+    (initial_bearing(to, from) + 180.0).rem_euclid(360.0)
+}
+
+/// Point at `fraction` of the great circle from `from` to `to`.
+pub(crate) fn interpolate(from: &LatLon, to: &LatLon, fraction: f64) -> LatLon {
+    let delta = distance(from, to) / EARTH_RADIUS;
+    if delta == 0.0 {
+        return from.clone();
+    }
+
+    let (lat1, lon1) = (from.lat.to_radians(), from.lon.to_radians());
+    let (lat2, lon2) = (to.lat.to_radians(), to.lon.to_radians());
+    let a = ((1.0 - fraction) * delta).sin() / delta.sin();
+    let b = (fraction * delta).sin() / delta.sin();
+
+    let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+    let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+    let z = a * lat1.sin() + b * lat2.sin();
+
+    LatLon {
+        lat: z.atan2((x * x + y * y).sqrt()).to_degrees(),
+        lon: normalize_lon(y.atan2(x).to_degrees()),
+    }
+}
+
+pub(crate) fn midpoint(a: &LatLon, b: &LatLon) -> LatLon {
+    interpolate(a, b, 0.5)
+}
+
+/// Points of the great circle from `from` to `to`, both included, at most `step` nautical miles
+/// apart.
+pub(crate) fn densify(from: &LatLon, to: &LatLon, step: f64) -> Vec<LatLon> {
+    let segments = (distance(from, to) / step).ceil().max(1.0) as usize;
+    (0..=segments)
+        .map(|i| match i {
+            0 => from.clone(),
+            i if i == segments => to.clone(),
+            i => interpolate(from, to, i as f64 / segments as f64),
+        })
+        .collect()
+}
+
+/// Point at `distance` nautical miles from `from` on the great circle starting at `bearing`.
+pub(crate) fn destination(from: &LatLon, bearing: f64, distance: f64) -> LatLon {
+    let (lat1, lon1) = (from.lat.to_radians(), from.lon.to_radians());
+    let (bearing, delta) = (bearing.to_radians(), distance / EARTH_RADIUS);
+
+    let lat2 = (lat1.sin() * delta.cos() + lat1.cos() * delta.sin() * bearing.cos()).asin();
+    let lon2 = lon1 + (bearing.sin() * delta.sin() * lat1.cos()).atan2(delta.cos() - lat1.sin() * lat2.sin());
+
+    LatLon { lat: lat2.to_degrees(), lon: normalize_lon(lon2.to_degrees()) }
+}
+
+/// Closed ring of `segments` points approximating the circle of `radius` nautical miles.
+pub(crate) fn circle(center: &LatLon, radius: f64, segments: usize) -> Vec<LatLon> {
+    let mut ring: Vec<LatLon> = (0..segments)
+        .map(|i| destination(center, 360.0 * i as f64 / segments as f64, radius))
+        .collect();
+    ring.push(ring[0].clone());
+    ring
+}
+
+/// Shifts the longitudes by multiples of 360 so that consecutive points are never more than 180
+/// apart, the shortest way round. The result can go beyond ±180 where the points cross the
+/// antimeridian.
+pub(crate) fn unwrap(points: &[LatLon]) -> Vec<LatLon> {
+    let mut res: Vec<LatLon> = Vec::with_capacity(points.len());
+    for point in points {
+        let lon = match res.last() {
+            Some(previous) => point.lon + 360.0 * ((previous.lon - point.lon) / 360.0).round(),
+            None => point.lon,
+        };
+        res.push(LatLon { lat: point.lat, lon });
+    }
+    res
+}
+
+/// Index of the 360° wide window of longitudes, centered on the Greenwich meridian for 0, that
+/// contains `lon`.
+fn window(lon: f64) -> i32 {
+    ((lon + 180.0) / 360.0).floor() as i32
+}
+
+/// Splits a line where it crosses the antimeridian, so that every part is within [-180, 180]. The
+/// longitudes must be continuous, as `unwrap` makes them: a line from 170 to 190 crosses the
+/// antimeridian, one from 170 to -170 goes the long way round.
+pub(crate) fn split_line(points: &[LatLon]) -> Vec<Vec<LatLon>> {
+    let mut parts = Vec::new();
+    let mut part: Vec<LatLon> = Vec::new();
+
+    for (index, point) in points.iter().enumerate() {
+        if index > 0 {
+            let previous = &points[index - 1];
+            let (from, to) = (window(previous.lon), window(point.lon));
+            // crosses one boundary per window, east or west
+            let mut current = from;
+            while current != to {
+                let east = to > current;
+                let boundary = if east { 180.0 + 360.0 * current as f64 } else { -180.0 + 360.0 * current as f64 };
+                let fraction = (boundary - previous.lon) / (point.lon - previous.lon);
+                let lat = previous.lat + fraction * (point.lat - previous.lat);
+
+                push(&mut part, LatLon { lat, lon: if east { 180.0 } else { -180.0 } });
+                parts.push(std::mem::take(&mut part));
+                push(&mut part, LatLon { lat, lon: if east { -180.0 } else { 180.0 } });
+                current += if east { 1 } else { -1 };
+            }
+        }
+        push(&mut part, LatLon { lat: point.lat, lon: point.lon - 360.0 * window(point.lon) as f64 });
+    }
+    parts.push(part);
+    parts.retain(|part| part.len() > 1);
+    parts
+}
+
+/// Appends a point to a line, unless it is its last one already, as happens for points lying on
+/// the antimeridian.
+fn push(line: &mut Vec<LatLon>, point: LatLon) {
+    match line.last() {
+        Some(last) if last.lat == point.lat && last.lon == point.lon => {},
+        _ => line.push(point),
+    }
+}
+
+/// Rings of a polygon with continuous longitudes, as `unwrap` makes them, its holes being shifted
+/// to the same side of the antimeridian as its exterior. The exterior comes first.
+pub(crate) fn unwrap_polygon(polygon: &Polygon) -> Vec<Vec<LatLon>> {
+    let exterior = unwrap(&polygon.exterior);
+    let reference = exterior.first().map(|p| p.lon);
+    let mut rings = vec![exterior];
+    for hole in &polygon.holes {
+        let mut hole = unwrap(hole);
+        if let (Some(reference), Some(first)) = (reference, hole.first()) {
+            let shift = 360.0 * ((reference - first.lon) / 360.0).round();
+            for point in &mut hole {
+                point.lon += shift;
+            }
+        }
+        rings.push(hole);
+    }
+    rings
+}
+
+/// Splits a polygon along the antimeridian, so that every part is a polygon within [-180, 180].
+pub(crate) fn split_polygon(polygon: &Polygon) -> Vec<Polygon> {
+    let rings = unwrap_polygon(polygon);
+    let windows = rings[0].iter().map(|p| window(p.lon));
+    let (min, max) = match (windows.clone().min(), windows.max()) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return Vec::new(),
+    };
+
+    (min..=max)
+        .filter_map(|window| {
+            let exterior = clip_window(&rings[0], window)?;
+            let holes = rings[1..].iter().filter_map(|hole| clip_window(hole, window)).collect();
+            Some(Polygon { exterior, holes })
+        })
+        .collect()
+}
+
+/// Part of a ring within a window of longitudes, closed and shifted to [-180, 180], `None` when
+/// too small to be a ring.
+fn clip_window(ring: &[LatLon], window: i32) -> Option<Vec<LatLon>> {
+    let offset = 360.0 * window as f64;
+    let part = clip(ring, |p| p.lon >= offset - 180.0, offset - 180.0);
+    let mut part = clip(&part, |p| p.lon <= offset + 180.0, offset + 180.0);
+    if part.len() < 3 {
+        return None;
+    }
+    for point in &mut part {
+        point.lon -= offset;
+    }
+    part.push(part[0].clone());
+    Some(part)
+}
+
+/// Clips a ring to the side of the meridian at `lon` where `inside` holds (Sutherland-Hodgman).
+/// The result is not closed.
+fn clip<F: Fn(&LatLon) -> bool>(ring: &[LatLon], inside: F, lon: f64) -> Vec<LatLon> {
+    let mut res = Vec::new();
+    let points = match (ring.first(), ring.last()) {
+        (Some(first), Some(last)) if ring.len() > 1 && first.lat == last.lat && first.lon == last.lon => &ring[..ring.len() - 1],
+        _ => ring,
+    };
+
+    for (index, point) in points.iter().enumerate() {
+        let previous = &points[(index + points.len() - 1) % points.len()];
+        let crossing = || {
+            let fraction = (lon - previous.lon) / (point.lon - previous.lon);
+            LatLon { lat: previous.lat + fraction * (point.lat - previous.lat), lon }
+        };
+        match (inside(previous), inside(point)) {
+            (true, true) => res.push(point.clone()),
+            (true, false) => res.push(crossing()),
+            (false, true) => {
+                res.push(crossing());
+                res.push(point.clone());
+            },
+            (false, false) => {},
+        }
+    }
+    res
+}
+
+/// Whether the point is inside the polygon and outside its holes. Edges are straight lines in the
+/// plane of longitudes and latitudes, as zones are drawn on charts.
+pub(crate) fn polygon_contains(polygon: &Polygon, point: &LatLon) -> bool {
+    let rings = unwrap_polygon(polygon);
+    shifted(point).iter().any(|point| rings_contain(&rings, point))
+}
+
+/// Whether a segment, straight in the plane of longitudes and latitudes, has a point inside the
+/// polygon: one of its ends is inside, or it crosses one of the rings.
+pub(crate) fn polygon_intersects(polygon: &Polygon, from: &LatLon, to: &LatLon) -> bool {
+    let rings = unwrap_polygon(polygon);
+    let segment = unwrap(&[from.clone(), to.clone()]);
+    shifted(&segment[0]).iter().zip(shifted(&segment[1]).iter())
+        .any(|(from, to)| {
+            rings_contain(&rings, from) || rings_contain(&rings, to)
+                || rings.iter().any(|ring| ring.windows(2).any(|edge| segments_cross(from, to, &edge[0], &edge[1])))
+        })
+}
+
+/// The point, and its copies a turn east and west, to compare with unwrapped longitudes.
+fn shifted(point: &LatLon) -> [LatLon; 3] {
+    [-360.0, 0.0, 360.0].map(|shift| LatLon { lat: point.lat, lon: point.lon + shift })
+}
+
+/// Whether the point is inside the first ring and outside the others.
+fn rings_contain(rings: &[Vec<LatLon>], point: &LatLon) -> bool {
+    match rings.split_first() {
+        Some((exterior, holes)) => ring_contains(exterior, point) && !holes.iter().any(|hole| ring_contains(hole, point)),
+        None => false,
+    }
+}
+
+/// Even-odd rule: the point is inside when a ray from it crosses the ring an odd number of times.
+fn ring_contains(ring: &[LatLon], point: &LatLon) -> bool {
+    let mut inside = false;
+    for edge in ring.windows(2) {
+        let (a, b) = (&edge[0], &edge[1]);
+        if (a.lat > point.lat) != (b.lat > point.lat) {
+            let lon = a.lon + (point.lat - a.lat) / (b.lat - a.lat) * (b.lon - a.lon);
+            if point.lon < lon {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Whether the segments `[a, b]` and `[c, d]` have a point in common.
+pub(crate) fn segments_cross(a: &LatLon, b: &LatLon, c: &LatLon, d: &LatLon) -> bool {
+    let (d1, d2) = (orientation(c, d, a), orientation(c, d, b));
+    let (d3, d4) = (orientation(a, b, c), orientation(a, b, d));
+    if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+        return true;
+    }
+    (d1 == 0.0 && within(c, d, a)) || (d2 == 0.0 && within(c, d, b))
+        || (d3 == 0.0 && within(a, b, c)) || (d4 == 0.0 && within(a, b, d))
+}
+
+/// Where the segment from `from` to `to` crosses the one from `a` to `b`: the fraction of the
+/// former at which it does, and whether `a` is then on its left, as a mark left to port. `None`
+/// when they don't cross, or are parallel.
+pub(crate) fn crossing(from: &LatLon, to: &LatLon, a: &LatLon, b: &LatLon) -> Option<(f64, bool)> {
+    let (rx, ry) = (to.lon - from.lon, to.lat - from.lat);
+    let (sx, sy) = (b.lon - a.lon, b.lat - a.lat);
+    let denominator = rx * sy - ry * sx;
+    if denominator == 0.0 {
+        return None
+    }
+    let (qx, qy) = (a.lon - from.lon, a.lat - from.lat);
+    let t = (qx * sy - qy * sx) / denominator;
+    let u = (qx * ry - qy * rx) / denominator;
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some((t, denominator < 0.0))
+    } else {
+        None
+    }
+}
+
+/// Sign of the turn from `a` to `b` to `c`: positive counterclockwise, zero when aligned.
+fn orientation(a: &LatLon, b: &LatLon, c: &LatLon) -> f64 {
+    (b.lon - a.lon) * (c.lat - a.lat) - (b.lat - a.lat) * (c.lon - a.lon)
+}
+
+/// Whether `point`, aligned with `a` and `b`, is between them.
+fn within(a: &LatLon, b: &LatLon, point: &LatLon) -> bool {
+    point.lon >= a.lon.min(b.lon) && point.lon <= a.lon.max(b.lon)
+        && point.lat >= a.lat.min(b.lat) && point.lat <= a.lat.max(b.lat)
+}
+
+/// Distance, in nautical miles, from the point to the nearest point of the line, `None` for an
+/// empty line. The segments of the line are straight in the plane of longitudes and latitudes, and
+/// its longitudes continuous.
+pub(crate) fn distance_to_line(point: &LatLon, line: &[LatLon]) -> Option<f64> {
+    if let [single] = line {
+        return Some(distance(point, single))
+    }
+    shifted(point).iter()
+        .flat_map(|point| line.windows(2).map(move |segment| distance(point, &nearest(point, &segment[0], &segment[1]))))
+        .reduce(f64::min)
+}
+
+/// Point of the segment from `a` to `b` nearest to `point`, in a projection where a degree of
+/// longitude is shortened as it is at the latitude of the point.
+fn nearest(point: &LatLon, a: &LatLon, b: &LatLon) -> LatLon {
+    let t = nearest_fraction(point, a, b);
+    LatLon { lat: a.lat + t * (b.lat - a.lat), lon: a.lon + t * (b.lon - a.lon) }
+}
+
+/// Fraction of the segment from `a` to `b` at which is its point nearest to `point`, as `nearest`.
+pub(crate) fn nearest_fraction(point: &LatLon, a: &LatLon, b: &LatLon) -> f64 {
+    let scale = point.lat.to_radians().cos();
+    let (dx, dy) = ((b.lon - a.lon) * scale, b.lat - a.lat);
+    let (px, py) = ((point.lon - a.lon) * scale, point.lat - a.lat);
+    let length = dx * dx + dy * dy;
+    if length == 0.0 { 0.0 } else { ((px * dx + py * dy) / length).clamp(0.0, 1.0) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::race::{LatLon, Polygon};
+
+    use super::{split_line, split_polygon, unwrap};
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    fn lons(points: &[LatLon]) -> Vec<f64> {
+        points.iter().map(|p| p.lon).collect()
+    }
+
+    #[test]
+    fn unwraps_longitudes_the_shortest_way_round() {
+        let points = [latlon(0.0, 170.0), latlon(0.0, -170.0), latlon(0.0, 175.0), latlon(0.0, -10.0)];
+
+        assert_eq!(lons(&unwrap(&points)), [170.0, 190.0, 175.0, 350.0]);
+    }
+
+    #[test]
+    fn splits_a_line_on_the_antimeridian() {
+        let parts = split_line(&unwrap(&[latlon(10.0, 170.0), latlon(20.0, -170.0)]));
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(lons(&parts[0]), [170.0, 180.0]);
+        assert_eq!(lons(&parts[1]), [-180.0, -170.0]);
+        assert_eq!(parts[0][1].lat, 15.0);
+        assert_eq!(parts[1][0].lat, 15.0);
+    }
+
+    #[test]
+    fn keeps_a_line_going_the_long_way_round_whole() {
+        let parts = split_line(&[latlon(10.0, 170.0), latlon(20.0, -170.0)]);
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(lons(&parts[0]), [170.0, -170.0]);
+    }
+
+    #[test]
+    fn splits_a_polygon_on_the_antimeridian() {
+        let polygon = Polygon {
+            exterior: vec![latlon(-10.0, 170.0), latlon(-10.0, -170.0), latlon(10.0, -170.0), latlon(10.0, 170.0), latlon(-10.0, 170.0)],
+            holes: Vec::new(),
+        };
+
+        let parts = split_polygon(&polygon);
+
+        assert_eq!(parts.len(), 2);
+        for part in &parts {
+            assert!(part.exterior.iter().all(|p| (-180.0..=180.0).contains(&p.lon)));
+            let (first, last) = (&part.exterior[0], &part.exterior[part.exterior.len() - 1]);
+            assert!(first.lat == last.lat && first.lon == last.lon);
+        }
+        let mut west: Vec<f64> = parts.iter()
+            .map(|part| part.exterior.iter().map(|p| p.lon).fold(f64::INFINITY, f64::min))
+            .collect();
+        west.sort_by(f64::total_cmp);
+        assert_eq!(west, [-180.0, 170.0]);
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use ::geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+use serde_json::json;
+
+use crate::geo;
+use crate::race::{LatLon, Polygon, Race};
+
+/// Number of sides of the polygons drawn for waypoints with a radius.
+const CIRCLE_SEGMENTS: usize = 64;
+
+/// Writes the geometry of a race as a GeoJSON FeatureCollection: its start, the gates and the
+/// circles of its waypoints, their zones to avoid and its ice limits. Geometries crossing the
+/// antimeridian are split along it, as RFC 7946 recommends.
+pub(crate) fn write(race: &Race) -> Result<String> {
+    let start = LatLon { lat: race.start.lat, lon: geo::normalize_lon(race.start.lon) };
+    let mut features = vec![
+        feature(Geometry::new(Value::Point(position(&start))), json!({ "kind": "start", "name": race.name })),
+    ];
+
+    for (index, waypoint) in race.waypoints.iter().enumerate() {
+        let geometry = match (waypoint.radius, waypoint.latlons.as_slice()) {
+            (Some(radius), [center]) => polygon(&Polygon { exterior: geo::circle(center, radius as f64, CIRCLE_SEGMENTS), holes: Vec::new() })
+                .map(|geometry| (geometry, json!({ "kind": waypoint.kind.name(), "index": index, "name": waypoint.name, "radius": radius }))),
+            (None, [a, b]) => line(&geo::unwrap(&[a.clone(), b.clone()]))
+                .map(|geometry| (geometry, json!({ "kind": waypoint.kind.name(), "index": index, "name": waypoint.name }))),
+            _ => None,
+        };
+        features.extend(geometry.map(|(geometry, properties)| feature(geometry, properties)));
+
+        for (zone, zone_polygon) in waypoint.to_avoid.iter().flat_map(|zones| &zones.0).enumerate() {
+            if let Some(geometry) = polygon(zone_polygon) {
+                features.push(feature(geometry, json!({ "kind": "toAvoid", "waypoint": index, "index": zone, "name": waypoint.name })));
+            }
+        }
+    }
+
+    if let Some(limits) = &race.ice_limits {
+        // the longitudes of the limits are sorted, from west to east
+        for (side, points) in [("north", &limits.north), ("south", &limits.south)] {
+            if let Some(geometry) = line(points) {
+                features.push(feature(geometry, json!({ "kind": "iceLimit", "side": side })));
+            }
+        }
+    }
+
+    let collection = FeatureCollection { bbox: None, features, foreign_members: None };
+    Ok(serde_json::to_string(&collection)?)
+}
+
+fn feature(geometry: Geometry, properties: serde_json::Value) -> Feature {
+    let properties: Option<JsonObject> = match properties {
+        serde_json::Value::Object(properties) => Some(properties),
+        _ => None,
+    };
+    Feature { bbox: None, geometry: Some(geometry), id: None, properties, foreign_members: None }
+}
+
+/// GeoJSON positions are [lon, lat].
+fn position(point: &LatLon) -> Vec<f64> {
+    vec![point.lon, point.lat]
+}
+
+fn line(points: &[LatLon]) -> Option<Geometry> {
+    let mut parts: Vec<Vec<Vec<f64>>> = geo::split_line(points).iter()
+        .map(|part| part.iter().map(position).collect())
+        .collect();
+    match parts.len() {
+        0 => None,
+        1 => Some(Geometry::new(Value::LineString(parts.remove(0)))),
+        _ => Some(Geometry::new(Value::MultiLineString(parts))),
+    }
+}
+
+fn polygon(polygon: &Polygon) -> Option<Geometry> {
+    let mut parts: Vec<Vec<Vec<Vec<f64>>>> = geo::split_polygon(polygon).iter()
+        .map(|part| std::iter::once(&part.exterior).chain(&part.holes)
+            .map(|ring| ring.iter().map(position).collect())
+            .collect())
+        .collect();
+    match parts.len() {
+        0 => None,
+        1 => Some(Geometry::new(Value::Polygon(parts.remove(0)))),
+        _ => Some(Geometry::new(Value::MultiPolygon(parts))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::race::{LatLon, Limits, Race, Waypoint, WaypointKind};
+
+    use super::write;
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    /// Start off Les Sables, then a gate, a buoy off Cape Finisterre and the finish: a point, a line
+    /// and circles.
+    fn race() -> Race {
+        let waypoint = |name: &str, kind: WaypointKind, radius: Option<u8>, latlons: Vec<LatLon>| Waypoint {
+            name: name.to_string(),
+            kind,
+            radius,
+            latlons,
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
+            to_avoid: None,
+        };
+        Race {
+            id: None,
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: latlon(46.5, -1.8),
+            waypoints: vec![
+                waypoint("1", WaypointKind::Gate, None, vec![latlon(45.0, -4.0), latlon(45.0, -5.0)]),
+                waypoint("2", WaypointKind::Buoy, Some(5), vec![latlon(43.0, -9.5)]),
+                waypoint("end", WaypointKind::Finish, Some(1), vec![latlon(46.4, -1.9)]),
+            ],
+            ice_limits: None,
+        }
+    }
+
+    fn features(document: &str) -> Vec<Value> {
+        let collection: Value = serde_json::from_str(document).unwrap();
+        collection["features"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn writes_the_start_and_every_waypoint_with_their_index() {
+        let features = features(&write(&race()).unwrap());
+
+        let kinds: Vec<(&str, &str)> = features.iter()
+            .map(|f| (f["properties"]["kind"].as_str().unwrap(), f["geometry"]["type"].as_str().unwrap()))
+            .collect();
+        assert_eq!(kinds, [("start", "Point"), ("gate", "LineString"), ("buoy", "Polygon"), ("finish", "Polygon")]);
+        assert_eq!(features[2]["properties"]["index"], 1);
+        assert_eq!(features[2]["properties"]["name"], "2");
+        // positions are [lon, lat]
+        assert_eq!(features[0]["geometry"]["coordinates"], json!([-1.8, 46.5]));
+    }
+
+    #[test]
+    fn splits_the_geometries_crossing_the_antimeridian() {
+        let mut race = race();
+        race.waypoints[0].latlons = vec![latlon(-50.0, 179.0), latlon(-52.0, -179.0)];
+        race.ice_limits = Some(Limits {
+            north: vec![latlon(-40.0, -180.0), latlon(-40.0, 180.0)],
+            south: vec![latlon(-60.0, -180.0), latlon(-60.0, 180.0)],
+            max_lat: 80.0,
+            min_lat: -60.0,
+        });
+
+        let features = features(&write(&race).unwrap());
+
+        let gate = &features[1]["geometry"];
+        assert_eq!(gate["type"], "MultiLineString");
+        assert_eq!(gate["coordinates"], json!([[[179.0, -50.0], [180.0, -51.0]], [[-180.0, -51.0], [-179.0, -52.0]]]));
+        let limits: Vec<&Value> = features.iter().filter(|f| f["properties"]["kind"] == "iceLimit").collect();
+        assert_eq!(limits.len(), 2);
+        assert!(limits.iter().all(|f| f["geometry"]["type"] == "LineString"));
+    }
+}
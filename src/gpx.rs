@@ -0,0 +1,334 @@
+use std::io::Write;
+
+use anyhow::Result;
+use chrono::SecondsFormat;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+
+use crate::course;
+use crate::geo;
+use crate::race::{Checkpoint, LatLon, Race, RaceError, Side, Waypoint, WaypointKind};
+use crate::xml::{empty_with, end, start, start_with, text};
+
+/// Namespace of the GPX extensions holding what GPX has no element for: the radius of buoys, the
+/// side marks are left on, and the group, engine flag and checkpoint of waypoints.
+const NAMESPACE: &str = "urn:races:gpx:1";
+
+/// Radius, in nautical miles, of the imported waypoints that are neither gates nor have a radius.
+const DEFAULT_RADIUS: u8 = 1;
+
+/// Writes a race as GPX: a route through its start and the mark of each of its waypoints, the
+/// midpoint of gates and the center of buoys, and a track for each gate.
+pub(crate) fn write(race: &Race) -> Result<String> {
+    let mut writer = crate::xml::writer()?;
+    start_with(&mut writer, "gpx", &[
+        ("version", "1.1"),
+        ("creator", "races"),
+        ("xmlns", "http://www.topografix.com/GPX/1/1"),
+        ("xmlns:races", NAMESPACE),
+    ])?;
+
+    start(&mut writer, "metadata")?;
+    text(&mut writer, "name", &race.name)?;
+    if let Some(start_time) = race.start_time {
+        text(&mut writer, "time", &start_time.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+    }
+    end(&mut writer, "metadata")?;
+
+    start(&mut writer, "rte")?;
+    text(&mut writer, "name", &race.name)?;
+    point(&mut writer, "rtept", &race.start, Some("Start"), Some("start"), None)?;
+    for waypoint in &race.waypoints {
+        if let Some(mark) = course::mark(waypoint) {
+            point(&mut writer, "rtept", &mark, Some(&waypoint.name), Some(waypoint.kind.name()), Some(waypoint))?;
+        }
+    }
+    end(&mut writer, "rte")?;
+
+    for waypoint in race.waypoints.iter().filter(|w| w.radius.is_none() && w.latlons.len() == 2) {
+        start(&mut writer, "trk")?;
+        text(&mut writer, "name", &waypoint.name)?;
+        text(&mut writer, "type", waypoint.kind.name())?;
+        start(&mut writer, "trkseg")?;
+        for latlon in &waypoint.latlons {
+            point(&mut writer, "trkpt", latlon, None, None, None)?;
+        }
+        end(&mut writer, "trkseg")?;
+        end(&mut writer, "trk")?;
+    }
+
+    end(&mut writer, "gpx")?;
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+fn point<W: Write>(writer: &mut Writer<W>, element: &str, latlon: &LatLon, name: Option<&str>, kind: Option<&str>, waypoint: Option<&Waypoint>) -> Result<()> {
+    let (lat, lon) = (latlon.lat.to_string(), geo::normalize_lon(latlon.lon).to_string());
+    let attributes = [("lat", lat.as_str()), ("lon", lon.as_str())];
+    if name.is_none() && kind.is_none() && waypoint.is_none() {
+        return empty_with(writer, element, &attributes)
+    }
+
+    start_with(writer, element, &attributes)?;
+    if let Some(name) = name {
+        text(writer, "name", name)?;
+    }
+    if let Some(kind) = kind {
+        text(writer, "type", kind)?;
+    }
+    if let Some(waypoint) = waypoint {
+        extensions(writer, waypoint)?;
+    }
+    end(writer, element)
+}
+
+/// Writes what GPX has no element for, if anything.
+fn extensions<W: Write>(writer: &mut Writer<W>, waypoint: &Waypoint) -> Result<()> {
+    let values = [
+        ("races:radius", waypoint.radius.map(|radius| radius.to_string())),
+        ("races:side", waypoint.side.map(|side| side.name().to_string())),
+        ("races:group", waypoint.group.map(|group| group.to_string())),
+        ("races:engine", waypoint.engine.map(|engine| engine.to_string())),
+    ];
+    if values.iter().all(|(_, value)| value.is_none()) && waypoint.checkpoint.is_none() {
+        return Ok(())
+    }
+
+    start(writer, "extensions")?;
+    for (element, value) in values {
+        if let Some(value) = value {
+            text(writer, element, &value)?;
+        }
+    }
+    if let Some(checkpoint) = &waypoint.checkpoint {
+        start(writer, "races:checkpoint")?;
+        text(writer, "races:id", &checkpoint.id.to_string())?;
+        text(writer, "races:name", &checkpoint.name)?;
+        end(writer, "races:checkpoint")?;
+    }
+    end(writer, "extensions")
+}
+
+/// Point of a GPX file, with what the races use of it.
+struct Point {
+    latlon: LatLon,
+    name: Option<String>,
+    kind: Option<String>,
+    radius: Option<u8>,
+    side: Option<Side>,
+    group: Option<u8>,
+    engine: Option<bool>,
+    checkpoint_id: Option<u8>,
+    checkpoint_name: Option<String>,
+}
+
+/// Reads the waypoints of a race from a GPX file: the points of its first route, or its waypoints
+/// when it has no route. The start of a route written by `write` is skipped. A point becomes a line
+/// when a track has its name, and a circle otherwise, of `DEFAULT_RADIUS` unless it has a radius.
+/// Waypoints are of the kind of their type, or of the kind `WaypointKind::infer` gives, and keep the
+/// side, group, engine flag and checkpoint of their extensions.
+pub(crate) fn read(gpx: &[u8]) -> Result<Vec<Waypoint>, RaceError> {
+    let invalid = |e: &dyn std::fmt::Display| RaceError::InvalidGpx(e.to_string());
+
+    let mut reader = Reader::from_reader(gpx);
+    reader.trim_text(true).expand_empty_elements(true);
+
+    let mut routes: Vec<Vec<Point>> = Vec::new();
+    let mut waypoints: Vec<Point> = Vec::new();
+    let mut tracks: Vec<(Option<String>, Vec<LatLon>)> = Vec::new();
+    // local names of the open elements, and the point being read with its depth
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut point: Option<(Point, usize)> = None;
+    let mut buf = Vec::new();
+
+    loop {
+        let text = match reader.read_event(&mut buf).map_err(|e| invalid(&e))? {
+            Event::Start(e) => {
+                match e.local_name() {
+                    b"rte" => routes.push(Vec::new()),
+                    b"trk" => tracks.push((None, Vec::new())),
+                    b"rtept" | b"wpt" | b"trkpt" => {
+                        let latlon = latlon(&reader, &e).map_err(|e| invalid(&e))?;
+                        let p = Point {
+                            latlon,
+                            name: None,
+                            kind: None,
+                            radius: None,
+                            side: None,
+                            group: None,
+                            engine: None,
+                            checkpoint_id: None,
+                            checkpoint_name: None,
+                        };
+                        point = Some((p, path.len()));
+                    },
+                    _ => {},
+                }
+                path.push(e.local_name().to_vec());
+                None
+            },
+            Event::End(_) => {
+                let element = path.pop().unwrap_or_default();
+                match (element.as_slice(), point.take()) {
+                    (b"rtept", Some((p, _))) => if let Some(route) = routes.last_mut() {
+                        route.push(p);
+                    },
+                    (b"wpt", Some((p, _))) => waypoints.push(p),
+                    (b"trkpt", Some((p, _))) => if let Some(track) = tracks.last_mut() {
+                        track.1.push(p.latlon);
+                    },
+                    (_, p) => point = p,
+                }
+                None
+            },
+            Event::Text(e) => Some(e.unescape_and_decode(&reader).map_err(|e| invalid(&e))?),
+            Event::CData(e) => Some(String::from_utf8_lossy(&e).to_string()),
+            Event::Eof => break,
+            _ => None,
+        };
+
+        if let Some(text) = text {
+            let element = path.last().map(Vec::as_slice);
+            let parent = path.len().checked_sub(2).map(|index| path[index].as_slice());
+            match &mut point {
+                Some((point, depth)) => match (element, parent) {
+                    (Some(b"name"), _) if path.len() == *depth + 2 => point.name = Some(text),
+                    (Some(b"type"), _) if path.len() == *depth + 2 => point.kind = Some(text),
+                    (Some(b"radius"), _) => point.radius = Some(text.trim().parse().map_err(|_| invalid(&format!("invalid radius {:?}", text)))?),
+                    (Some(b"side"), _) => point.side = match text.trim() {
+                        "port" => Some(Side::Port),
+                        "stbd" => Some(Side::Stbd),
+                        _ => return Err(invalid(&format!("invalid side {:?}", text))),
+                    },
+                    (Some(b"group"), _) => point.group = Some(text.trim().parse().map_err(|_| invalid(&format!("invalid group {:?}", text)))?),
+                    (Some(b"engine"), _) => point.engine = Some(text.trim().parse().map_err(|_| invalid(&format!("invalid engine {:?}", text)))?),
+                    (Some(b"id"), Some(b"checkpoint")) => point.checkpoint_id = Some(text.trim().parse().map_err(|_| invalid(&format!("invalid checkpoint {:?}", text)))?),
+                    (Some(b"name"), Some(b"checkpoint")) => point.checkpoint_name = Some(text),
+                    _ => {},
+                },
+                None => if element == Some(b"name") && path.len() > 1 && path[path.len() - 2] == b"trk" {
+                    if let Some(track) = tracks.last_mut() {
+                        track.0 = Some(text);
+                    }
+                },
+            }
+        }
+        buf.clear();
+    }
+
+    let points = routes.into_iter().find(|route| !route.is_empty()).unwrap_or(waypoints);
+    let points: Vec<Point> = points.into_iter().filter(|p| p.kind.as_deref() != Some("start")).collect();
+    if points.is_empty() {
+        return Err(invalid(&"no route nor waypoint"))
+    }
+
+    let count = points.len();
+    Ok(points.into_iter()
+        .enumerate()
+        .map(|(index, point)| {
+            let name = point.name.unwrap_or_else(|| (index + 1).to_string());
+            let line = tracks.iter()
+                .find(|(track, latlons)| track.as_deref() == Some(name.as_str()) && latlons.len() >= 2)
+                .map(|(_, latlons)| vec![latlons[0].clone(), latlons[latlons.len() - 1].clone()]);
+            let (radius, latlons) = match line {
+                Some(latlons) => (None, latlons),
+                None => (Some(point.radius.unwrap_or(DEFAULT_RADIUS)), vec![point.latlon]),
+            };
+            let kind = match point.kind.as_deref() {
+                Some("gate") => WaypointKind::Gate,
+                Some("buoy") => WaypointKind::Buoy,
+                Some("finish") => WaypointKind::Finish,
+                _ => WaypointKind::infer(radius, index + 1 == count),
+            };
+            let checkpoint = point.checkpoint_id.map(|id| Checkpoint { id, name: point.checkpoint_name.unwrap_or_default() });
+            Waypoint { name, kind, radius, latlons, side: point.side, group: point.group, engine: point.engine, checkpoint, to_avoid: None }
+        })
+        .collect())
+}
+
+fn latlon(reader: &Reader<&[u8]>, element: &BytesStart) -> Result<LatLon, String> {
+    let (mut lat, mut lon) = (None, None);
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        let value = || -> Result<f64, String> {
+            let value = attribute.unescape_and_decode_value(reader).map_err(|e| e.to_string())?;
+            value.trim().parse().map_err(|_| format!("invalid coordinate {:?}", value))
+        };
+        match attribute.key {
+            b"lat" => lat = Some(value()?),
+            b"lon" => lon = Some(value()?),
+            _ => {},
+        }
+    }
+    match (lat, lon) {
+        (Some(lat), Some(lon)) => Ok(LatLon { lat, lon }),
+        _ => Err("a point must have lat and lon attributes".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::race::{Checkpoint, LatLon, Race, Side, Waypoint, WaypointKind};
+
+    use super::{read, write};
+
+    #[test]
+    fn reads_back_the_waypoints_it_writes() {
+        let waypoint = |name: &str, kind: WaypointKind, radius: Option<u8>, latlons: Vec<LatLon>| Waypoint {
+            name: name.to_string(),
+            kind,
+            radius,
+            latlons,
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
+            to_avoid: None,
+        };
+        let mut race = Race {
+            id: None,
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: LatLon { lat: 46.5, lon: -1.8 },
+            waypoints: vec![
+                waypoint("1", WaypointKind::Gate, None, vec![LatLon { lat: 45.0, lon: -4.0 }, LatLon { lat: 45.0, lon: -5.0 }]),
+                waypoint("2", WaypointKind::Buoy, Some(5), vec![LatLon { lat: 43.0, lon: -9.5 }]),
+                waypoint("end", WaypointKind::Finish, Some(1), vec![LatLon { lat: 46.4, lon: -1.9 }]),
+            ],
+            ice_limits: None,
+        };
+
+        race.waypoints[0].side = Some(Side::Stbd);
+        race.waypoints[1].group = Some(2);
+        race.waypoints[1].engine = Some(false);
+        race.waypoints[1].checkpoint = Some(Checkpoint { id: 7, name: String::from("Cap Finisterre") });
+
+        let waypoints = read(write(&race).unwrap().as_bytes()).unwrap();
+
+        assert_eq!(serde_json::to_value(&waypoints).unwrap(), serde_json::to_value(&race.waypoints).unwrap());
+    }
+
+    #[test]
+    fn reads_waypoints_without_route() {
+        let gpx = r#"<?xml version="1.0"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+                <wpt lat="45.0" lon="-4.0"><name>A</name></wpt>
+                <wpt lat="43.0" lon="-9.5"><name>B</name></wpt>
+            </gpx>"#;
+
+        let waypoints = read(gpx.as_bytes()).unwrap();
+
+        let kinds: Vec<(&str, WaypointKind, Option<u8>)> = waypoints.iter().map(|w| (w.name.as_str(), w.kind, w.radius)).collect();
+        assert_eq!(kinds, [("A", WaypointKind::Buoy, Some(1)), ("B", WaypointKind::Finish, Some(1))]);
+    }
+
+    #[test]
+    fn rejects_files_without_points() {
+        assert!(read(b"<gpx version=\"1.1\"></gpx>").is_err());
+        assert!(read(b"<gpx><wpt lat=\"north\" lon=\"0\"/></gpx>").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::race::{LatLon, Limits, Race, Waypoint};
+
+/// Immutable snapshot of a race, recorded on every change.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct Revision {
+    pub(crate) number: u32,
+    pub(crate) timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) author: Option<String>,
+    pub(crate) action: Action,
+    #[serde(with = "crate::schema")]
+    pub(crate) race: Race,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Action {
+    Create,
+    Update,
+    Archive,
+    Restore,
+    Delete,
+    Rollback,
+}
+
+/// Commit of a versioned store.
+#[derive(Debug, Clone)]
+pub(crate) struct Commit {
+    pub(crate) id: String,
+    pub(crate) message: String,
+    pub(crate) author: String,
+    pub(crate) email: String,
+    pub(crate) timestamp: DateTime<Utc>,
+}
+
+/// Changes between two revisions of a race.
+#[derive(Debug, Default)]
+pub(crate) struct RaceDiff {
+    pub(crate) fields: Vec<FieldChange>,
+    pub(crate) waypoints: Vec<WaypointChange>,
+    pub(crate) ice_limits: Vec<FieldChange>,
+}
+
+#[derive(Debug)]
+pub(crate) struct FieldChange {
+    pub(crate) field: String,
+    pub(crate) from: Value,
+    pub(crate) to: Value,
+}
+
+#[derive(Debug)]
+pub(crate) enum WaypointChange {
+    Added { index: usize, waypoint: Waypoint },
+    Removed { index: usize, waypoint: Waypoint },
+    Moved { from_index: usize, to_index: usize, name: String, from: Vec<LatLon>, to: Vec<LatLon> },
+    Modified { from_index: usize, to_index: usize, name: String, changes: Vec<FieldChange> },
+}
+
+fn compare<T: Serialize>(changes: &mut Vec<FieldChange>, field: &str, from: &T, to: &T) {
+    let from = serde_json::to_value(from).unwrap_or(Value::Null);
+    let to = serde_json::to_value(to).unwrap_or(Value::Null);
+    if from != to {
+        changes.push(FieldChange { field: field.to_string(), from, to });
+    }
+}
+
+fn same_position(from: &[LatLon], to: &[LatLon]) -> bool {
+    from.len() == to.len() && from.iter().zip(to).all(|(a, b)| a.lat == b.lat && a.lon == b.lon)
+}
+
+/// Waypoints are paired by name, in order, so that inserting a waypoint doesn't show every
+/// following one as modified.
+fn diff_waypoints(from: &[Waypoint], to: &[Waypoint]) -> Vec<WaypointChange> {
+    let mut res = Vec::new();
+    let mut matched = vec![false; to.len()];
+
+    for (from_index, waypoint) in from.iter().enumerate() {
+        let to_index = to.iter().enumerate()
+            .position(|(i, w)| !matched[i] && w.name == waypoint.name);
+
+        match to_index {
+            Some(to_index) => {
+                matched[to_index] = true;
+                let other = &to[to_index];
+                if !same_position(&waypoint.latlons, &other.latlons) {
+                    res.push(WaypointChange::Moved {
+                        from_index,
+                        to_index,
+                        name: waypoint.name.clone(),
+                        from: waypoint.latlons.clone(),
+                        to: other.latlons.clone(),
+                    });
+                }
+                let mut changes = Vec::new();
+                compare(&mut changes, "kind", &waypoint.kind, &other.kind);
+                compare(&mut changes, "radius", &waypoint.radius, &other.radius);
+                compare(&mut changes, "side", &waypoint.side, &other.side);
+                compare(&mut changes, "group", &waypoint.group, &other.group);
+                compare(&mut changes, "engine", &waypoint.engine, &other.engine);
+                compare(&mut changes, "checkpoint", &waypoint.checkpoint, &other.checkpoint);
+                compare(&mut changes, "toAvoid", &waypoint.to_avoid, &other.to_avoid);
+                if !changes.is_empty() {
+                    res.push(WaypointChange::Modified { from_index, to_index, name: waypoint.name.clone(), changes });
+                }
+            },
+            None => res.push(WaypointChange::Removed { index: from_index, waypoint: waypoint.clone() }),
+        }
+    }
+
+    for (index, waypoint) in to.iter().enumerate() {
+        if !matched[index] {
+            res.push(WaypointChange::Added { index, waypoint: waypoint.clone() });
+        }
+    }
+
+    res
+}
+
+fn diff_limits(from: &Option<Limits>, to: &Option<Limits>) -> Vec<FieldChange> {
+    let mut res = Vec::new();
+    match (from, to) {
+        (Some(from), Some(to)) => {
+            compare(&mut res, "north", &from.north, &to.north);
+            compare(&mut res, "south", &from.south, &to.south);
+            compare(&mut res, "maxLat", &from.max_lat, &to.max_lat);
+            compare(&mut res, "minLat", &from.min_lat, &to.min_lat);
+        },
+        _ => compare(&mut res, "ice_limits", from, to),
+    }
+    res
+}
+
+pub(crate) fn diff(from: &Race, to: &Race) -> RaceDiff {
+    let mut fields = Vec::new();
+    compare(&mut fields, "race_id", &from.race_id, &to.race_id);
+    compare(&mut fields, "name", &from.name, &to.name);
+    compare(&mut fields, "shortName", &from.short_name, &to.short_name);
+    compare(&mut fields, "boat", &from.boat, &to.boat);
+    compare(&mut fields, "start_time", &from.start_time, &to.start_time);
+    compare(&mut fields, "end_time", &from.end_time, &to.end_time);
+    compare(&mut fields, "start", &from.start, &to.start);
+
+    RaceDiff {
+        fields,
+        waypoints: diff_waypoints(&from.waypoints, &to.waypoints),
+        ice_limits: diff_limits(&from.ice_limits, &to.ice_limits),
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use chrono::SecondsFormat;
+use quick_xml::Writer;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::geo;
+use crate::race::{LatLon, Polygon, Race, Waypoint, WaypointKind};
+use crate::xml::{end, start, start_with, text};
+
+/// Number of sides of the polygons drawn for buoys.
+const CIRCLE_SEGMENTS: usize = 64;
+
+/// Style of a kind of placemark, colors being `aabbggrr`.
+struct Style {
+    id: &'static str,
+    color: &'static str,
+    /// icon of points, lines being drawn `width` wide when there is none
+    icon: Option<&'static str>,
+    width: u8,
+    fill: Option<&'static str>,
+}
+
+/// Styles of the start, of each kind of waypoint, named after it, and of the zones and limits.
+const STYLES: [Style; 6] = [
+    Style { id: "start", color: "ff00ff00", icon: Some("http://maps.google.com/mapfiles/kml/shapes/flag.png"), width: 0, fill: None },
+    Style { id: "gate", color: "ff00ffff", icon: None, width: 3, fill: None },
+    Style { id: "buoy", color: "ff00a5ff", icon: None, width: 2, fill: Some("4000a5ff") },
+    Style { id: "finish", color: "ffff00ff", icon: None, width: 3, fill: Some("40ff00ff") },
+    Style { id: "toAvoid", color: "ff0000ff", icon: None, width: 2, fill: Some("600000ff") },
+    Style { id: "iceLimit", color: "ffffff00", icon: None, width: 2, fill: None },
+];
+
+enum Shape {
+    Point(LatLon),
+    Lines(Vec<Vec<LatLon>>),
+    Polygons(Vec<Polygon>),
+}
+
+/// Writes a race as a KML document, with a styled folder for its start, each kind of waypoint,
+/// zones to avoid and ice limits. Every placemark carries the name and the times of the race.
+pub(crate) fn write(race: &Race) -> Result<String> {
+    let mut writer = crate::xml::writer()?;
+    start_with(&mut writer, "kml", &[("xmlns", "http://www.opengis.net/kml/2.2")])?;
+    start(&mut writer, "Document")?;
+    text(&mut writer, "name", &race.name)?;
+    for style in &STYLES {
+        self::style(&mut writer, style)?;
+    }
+
+    let start_point = Shape::Point(LatLon { lat: race.start.lat, lon: geo::normalize_lon(race.start.lon) });
+    folder(&mut writer, race, "Start", vec![("Start".to_string(), "start", start_point)])?;
+
+    for (kind, name) in [(WaypointKind::Gate, "Gates"), (WaypointKind::Buoy, "Buoys"), (WaypointKind::Finish, "Finish")] {
+        let waypoints = race.waypoints.iter()
+            .filter(|w| w.kind == kind)
+            .filter_map(|w| shape(w).map(|shape| (w.name.clone(), kind.name(), shape)))
+            .collect();
+        folder(&mut writer, race, name, waypoints)?;
+    }
+
+    let zones = race.waypoints.iter()
+        .flat_map(|w| w.to_avoid.iter().flat_map(|zones| &zones.0).enumerate().map(move |(index, zone)| (w, index, zone)))
+        .map(|(w, index, zone)| (format!("{} #{}", w.name, index + 1), "toAvoid", Shape::Polygons(geo::split_polygon(zone))))
+        .collect();
+    folder(&mut writer, race, "Zones to avoid", zones)?;
+
+    let limits = race.ice_limits.iter()
+        .flat_map(|limits| [("North", &limits.north), ("South", &limits.south)])
+        // the longitudes of the limits are sorted, from west to east
+        .map(|(side, points)| (side.to_string(), "iceLimit", Shape::Lines(geo::split_line(points))))
+        .collect();
+    folder(&mut writer, race, "Ice limits", limits)?;
+
+    end(&mut writer, "Document")?;
+    end(&mut writer, "kml")?;
+    Ok(String::from_utf8(writer.into_inner())?)
+}
+
+/// Writes a race as a KMZ archive, the KML document zipped.
+pub(crate) fn write_kmz(race: &Race) -> Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("doc.kml", FileOptions::default())?;
+    zip.write_all(write(race)?.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Line of a waypoint, or circle around its center when it has a radius, whatever its kind.
+fn shape(waypoint: &Waypoint) -> Option<Shape> {
+    match (waypoint.radius, waypoint.latlons.as_slice()) {
+        (Some(radius), [center]) => {
+            let circle = Polygon { exterior: geo::circle(center, radius as f64, CIRCLE_SEGMENTS), holes: Vec::new() };
+            Some(Shape::Polygons(geo::split_polygon(&circle)))
+        },
+        (None, line) if line.len() >= 2 => Some(Shape::Lines(geo::split_line(&geo::unwrap(line)))),
+        _ => None,
+    }
+}
+
+fn style<W: Write>(writer: &mut Writer<W>, style: &Style) -> Result<()> {
+    start_with(writer, "Style", &[("id", style.id)])?;
+    match style.icon {
+        Some(icon) => {
+            start(writer, "IconStyle")?;
+            text(writer, "color", style.color)?;
+            start(writer, "Icon")?;
+            text(writer, "href", icon)?;
+            end(writer, "Icon")?;
+            end(writer, "IconStyle")?;
+        },
+        None => {
+            start(writer, "LineStyle")?;
+            text(writer, "color", style.color)?;
+            text(writer, "width", &style.width.to_string())?;
+            end(writer, "LineStyle")?;
+        },
+    }
+    if let Some(fill) = style.fill {
+        start(writer, "PolyStyle")?;
+        text(writer, "color", fill)?;
+        end(writer, "PolyStyle")?;
+    }
+    end(writer, "Style")
+}
+
+fn folder<W: Write>(writer: &mut Writer<W>, race: &Race, name: &str, placemarks: Vec<(String, &str, Shape)>) -> Result<()> {
+    start(writer, "Folder")?;
+    text(writer, "name", name)?;
+    for (name, style, shape) in placemarks {
+        // shapes too small to be drawn are left out
+        let empty = match &shape {
+            Shape::Point(_) => false,
+            Shape::Lines(parts) => parts.is_empty(),
+            Shape::Polygons(parts) => parts.is_empty(),
+        };
+        if !empty {
+            placemark(writer, race, &name, style, &shape)?;
+        }
+    }
+    end(writer, "Folder")
+}
+
+fn placemark<W: Write>(writer: &mut Writer<W>, race: &Race, name: &str, style: &str, shape: &Shape) -> Result<()> {
+    let times = [
+        ("start_time", race.start_time.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))),
+        ("end_time", race.end_time.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))),
+    ];
+
+    start(writer, "Placemark")?;
+    text(writer, "name", name)?;
+    if let [(_, Some(begin)), (_, end_time)] = &times {
+        start(writer, "TimeSpan")?;
+        text(writer, "begin", begin)?;
+        if let Some(end_time) = end_time {
+            text(writer, "end", end_time)?;
+        }
+        end(writer, "TimeSpan")?;
+    }
+    text(writer, "styleUrl", &format!("#{}", style))?;
+
+    start(writer, "ExtendedData")?;
+    for (name, value) in [("race", Some(race.name.clone()))].into_iter().chain(times) {
+        if let Some(value) = value {
+            start_with(writer, "Data", &[("name", name)])?;
+            text(writer, "value", &value)?;
+            end(writer, "Data")?;
+        }
+    }
+    end(writer, "ExtendedData")?;
+
+    match shape {
+        Shape::Point(point) => {
+            start(writer, "Point")?;
+            text(writer, "coordinates", &coordinates(std::slice::from_ref(point)))?;
+            end(writer, "Point")?;
+        },
+        Shape::Lines(lines) => geometries(writer, lines, |writer, line| {
+            start(writer, "LineString")?;
+            text(writer, "tessellate", "1")?;
+            text(writer, "coordinates", &coordinates(line))?;
+            end(writer, "LineString")
+        })?,
+        Shape::Polygons(polygons) => geometries(writer, polygons, |writer, polygon| {
+            start(writer, "Polygon")?;
+            boundary(writer, "outerBoundaryIs", &polygon.exterior)?;
+            for hole in &polygon.holes {
+                boundary(writer, "innerBoundaryIs", hole)?;
+            }
+            end(writer, "Polygon")
+        })?,
+    }
+    end(writer, "Placemark")
+}
+
+fn boundary<W: Write>(writer: &mut Writer<W>, name: &str, ring: &[LatLon]) -> Result<()> {
+    start(writer, name)?;
+    start(writer, "LinearRing")?;
+    text(writer, "coordinates", &coordinates(ring))?;
+    end(writer, "LinearRing")?;
+    end(writer, name)
+}
+
+/// Writes a geometry per part, grouped in a `MultiGeometry` when there are several, as for shapes
+/// split along the antimeridian.
+fn geometries<W: Write, T, F>(writer: &mut Writer<W>, parts: &[T], geometry: F) -> Result<()>
+    where F: Fn(&mut Writer<W>, &T) -> Result<()> {
+    if parts.len() == 1 {
+        return geometry(writer, &parts[0])
+    }
+    start(writer, "MultiGeometry")?;
+    for part in parts {
+        geometry(writer, part)?;
+    }
+    end(writer, "MultiGeometry")
+}
+
+/// KML coordinates are `lon,lat` tuples, separated by spaces.
+fn coordinates(points: &[LatLon]) -> String {
+    points.iter()
+        .map(|p| format!("{},{}", p.lon, p.lat))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::race::{LatLon, MultiPolygon, Polygon, Race, Waypoint, WaypointKind};
+
+    use super::write;
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    /// Waypoint with its geometry only.
+    fn waypoint(name: &str, kind: WaypointKind, radius: Option<u8>, latlons: Vec<LatLon>) -> Waypoint {
+        Waypoint {
+            name: name.to_string(),
+            kind,
+            radius,
+            latlons,
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
+            to_avoid: None,
+        }
+    }
+
+    /// Gate, buoy and finish, each written in its own folder.
+    fn race() -> Race {
+        Race {
+            id: None,
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: Some(Utc.ymd(2021, 11, 7).and_hms(12, 0, 0)),
+            end_time: None,
+            start: latlon(46.5, -1.8),
+            waypoints: vec![
+                waypoint("1", WaypointKind::Gate, None, vec![latlon(45.0, -4.0), latlon(45.0, -5.0)]),
+                waypoint("2", WaypointKind::Buoy, Some(5), vec![latlon(43.0, -9.5)]),
+                waypoint("end", WaypointKind::Finish, Some(1), vec![latlon(46.4, -1.9)]),
+            ],
+            ice_limits: None,
+        }
+    }
+
+    /// Names of the placemarks of each folder, in order.
+    fn folders(kml: &str) -> Vec<(String, Vec<String>)> {
+        kml.split("<Folder>").skip(1)
+            .map(|folder| {
+                let mut names = folder.split("<name>").skip(1).map(|name| name[..name.find('<').unwrap()].to_string());
+                (names.next().unwrap(), names.filter(|name| name != "Test").collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_a_folder_for_each_kind_of_mark() {
+        let mut race = race();
+        // a buoy rounded through a line, as imported from legs
+        race.waypoints.insert(1, waypoint("mark", WaypointKind::Buoy, None, vec![latlon(44.0, -6.0), latlon(44.0, -7.0)]));
+        race.waypoints[0].to_avoid = Some(MultiPolygon(vec![Polygon {
+            exterior: vec![latlon(44.0, -3.0), latlon(44.0, -2.0), latlon(43.5, -2.5), latlon(44.0, -3.0)],
+            holes: Vec::new(),
+        }]));
+
+        let folders = folders(&write(&race).unwrap());
+
+        let names: Vec<(&str, Vec<&str>)> = folders.iter()
+            .map(|(folder, names)| (folder.as_str(), names.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(names, [
+            ("Start", vec!["Start"]),
+            ("Gates", vec!["1"]),
+            ("Buoys", vec!["mark", "2"]),
+            ("Finish", vec!["end"]),
+            ("Zones to avoid", vec!["1 #1"]),
+            ("Ice limits", vec![]),
+        ]);
+    }
+
+    #[test]
+    fn styles_marks_by_kind_and_carries_the_times_of_the_race() {
+        let kml = write(&race()).unwrap();
+
+        for style in ["#start", "#gate", "#buoy", "#finish"] {
+            assert!(kml.contains(&format!("<styleUrl>{}</styleUrl>", style)), "{}", style);
+        }
+        assert!(kml.contains("2021-11-07T12:00:00Z"));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::geo;
+use crate::race::{LatLon, Limits};
+
+/// Bound of the sea area of a race.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Limit {
+    /// the north line, not to be sailed north of
+    North,
+    /// the south line, not to be sailed south of
+    South,
+    MaxLat,
+    MinLat,
+}
+
+/// How a position stands with the ice limits of a race. Latitudes are in degrees and distances in
+/// nautical miles.
+#[derive(Debug)]
+pub(crate) struct Evaluation {
+    pub(crate) position: LatLon,
+    pub(crate) respects: bool,
+    /// latitude of the north line at the longitude of the position, `None` where it doesn't go
+    pub(crate) north: Option<f64>,
+    /// latitude of the south line at the longitude of the position, `None` where it doesn't go
+    pub(crate) south: Option<f64>,
+    /// nearest limit, with its distance
+    pub(crate) nearest: Option<(Limit, f64)>,
+}
+
+/// How a path stands with the ice limits of a race: the evaluations of its positions, and the
+/// limits its segments cross, each segment being straight in the plane of longitudes and latitudes.
+#[derive(Debug)]
+pub(crate) struct PathEvaluation {
+    pub(crate) respects: bool,
+    pub(crate) positions: Vec<Evaluation>,
+    pub(crate) crossed: Vec<Limit>,
+}
+
+/// Evaluates a position: it respects the limits when it is between `minLat` and `maxLat`, south
+/// of the north line and north of the south line, each line being interpolated linearly between
+/// its points.
+pub(crate) fn evaluate(limits: &Limits, position: &LatLon) -> Evaluation {
+    let north = latitude_at(&limits.north, position.lon);
+    let south = latitude_at(&limits.south, position.lon);
+    let respects = position.lat <= limits.max_lat && position.lat >= limits.min_lat
+        && north.is_none_or(|north| position.lat <= north)
+        && south.is_none_or(|south| position.lat >= south);
+
+    let along_meridian = |lat: f64| geo::EARTH_RADIUS * lat.abs().to_radians();
+    let nearest = [
+        (Limit::North, geo::distance_to_line(position, &limits.north)),
+        (Limit::South, geo::distance_to_line(position, &limits.south)),
+        (Limit::MaxLat, Some(along_meridian(limits.max_lat - position.lat))),
+        (Limit::MinLat, Some(along_meridian(position.lat - limits.min_lat))),
+    ]
+        .into_iter()
+        .filter_map(|(limit, distance)| distance.map(|distance| (limit, distance)))
+        .reduce(|a, b| if b.1 < a.1 { b } else { a });
+
+    Evaluation { position: position.clone(), respects, north, south, nearest }
+}
+
+/// Evaluates a path, a single position or the segments between consecutive ones: it respects the
+/// limits when all its positions do, and its segments cross none of them.
+pub(crate) fn evaluate_path(limits: &Limits, path: &[LatLon]) -> PathEvaluation {
+    let positions: Vec<Evaluation> = path.iter().map(|position| evaluate(limits, position)).collect();
+
+    let mut crossed = Vec::new();
+    for segment in path.windows(2) {
+        let segment = geo::unwrap(segment);
+        for (limit, line) in [(Limit::North, &limits.north), (Limit::South, &limits.south)] {
+            if !crossed.contains(&limit) && crosses(&segment, line) {
+                crossed.push(limit);
+            }
+        }
+    }
+    // latitudes vary monotonically along a segment, which goes beyond a parallel only at its ends
+    if path.iter().any(|p| p.lat > limits.max_lat) {
+        crossed.push(Limit::MaxLat);
+    }
+    if path.iter().any(|p| p.lat < limits.min_lat) {
+        crossed.push(Limit::MinLat);
+    }
+
+    let respects = positions.iter().all(|p| p.respects) && crossed.is_empty();
+    PathEvaluation { respects, positions, crossed }
+}
+
+/// Latitude of a line at a longitude, trying the longitude a turn east and west too, as the line
+/// can go beyond ±180.
+fn latitude_at(line: &[LatLon], lon: f64) -> Option<f64> {
+    [lon, lon + 360.0, lon - 360.0].iter().find_map(|&lon| {
+        line.windows(2).find_map(|segment| {
+            let (a, b) = (&segment[0], &segment[1]);
+            if lon < a.lon || lon > b.lon {
+                None
+            } else if a.lon == b.lon {
+                Some(a.lat)
+            } else {
+                Some(a.lat + (lon - a.lon) / (b.lon - a.lon) * (b.lat - a.lat))
+            }
+        })
+    })
+}
+
+fn crosses(segment: &[LatLon], line: &[LatLon]) -> bool {
+    [-360.0, 0.0, 360.0].iter().any(|shift| {
+        let from = LatLon { lat: segment[0].lat, lon: segment[0].lon + shift };
+        let to = LatLon { lat: segment[1].lat, lon: segment[1].lon + shift };
+        line.windows(2).any(|edge| geo::segments_cross(&from, &to, &edge[0], &edge[1]))
+    })
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use crate::geo;
+use crate::race::{LatLon, Polygon, Race};
+
+/// Range the longitudes of races are normalized to when they are created or updated.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Longitudes {
+    /// [-180, 180)
+    #[default]
+    Signed,
+    /// [0, 360)
+    Positive,
+}
+
+impl Longitudes {
+    /// West end of the range.
+    fn west(self) -> f64 {
+        match self {
+            Longitudes::Signed => -180.0,
+            Longitudes::Positive => 0.0,
+        }
+    }
+
+    pub(crate) fn normalize(self, lon: f64) -> f64 {
+        match self {
+            Longitudes::Signed => geo::normalize_lon(lon),
+            // untouched, rather than rounded by the arithmetic
+            Longitudes::Positive if (0.0..360.0).contains(&lon) => lon,
+            Longitudes::Positive => lon.rem_euclid(360.0),
+        }
+    }
+}
+
+/// Normalizes the longitudes of a race. Points, gates and zones to avoid are normalized point by
+/// point, as their edges are never more than half a turn long, and every computation makes them
+/// continuous again. Ice limits must stay sorted: a limit going round the world is cut at the west
+/// end of the range, and any other is shifted by whole turns.
+pub(crate) fn normalize(race: &mut Race, longitudes: Longitudes) {
+    let point = |point: &mut LatLon| point.lon = longitudes.normalize(point.lon);
+
+    point(&mut race.start);
+    for waypoint in &mut race.waypoints {
+        waypoint.latlons.iter_mut().for_each(point);
+        for Polygon { exterior, holes } in waypoint.to_avoid.iter_mut().flat_map(|zones| &mut zones.0) {
+            exterior.iter_mut().chain(holes.iter_mut().flatten()).for_each(point);
+        }
+    }
+    if let Some(limits) = &mut race.ice_limits {
+        limits.north = limit(&limits.north, longitudes);
+        limits.south = limit(&limits.south, longitudes);
+    }
+}
+
+fn limit(line: &[LatLon], longitudes: Longitudes) -> Vec<LatLon> {
+    let (first, last) = match (line.first(), line.last()) {
+        (Some(first), Some(last)) => (first.lon, last.lon),
+        _ => return line.to_vec(),
+    };
+    let west = longitudes.west();
+
+    if last - first == 360.0 {
+        if first == west {
+            return line.to_vec()
+        }
+        // the copies of the line a turn east and west, within the range, between its ends
+        let lat = latitude_at(line, west);
+        let mut res = vec![LatLon { lat, lon: west }];
+        for shift in [-360.0, 0.0, 360.0] {
+            for p in line.iter().map(|p| LatLon { lat: p.lat, lon: p.lon + shift }) {
+                // the ends of the line are the same point, a turn apart
+                let duplicate = res.last().is_some_and(|last: &LatLon| last.lat == p.lat && last.lon == p.lon);
+                if p.lon > west && p.lon < west + 360.0 && !duplicate {
+                    res.push(p);
+                }
+            }
+        }
+        res.push(LatLon { lat, lon: west + 360.0 });
+        return res
+    }
+
+    // whole turns to shift the line by, so that it starts within the range, or at least that it
+    // stays within the valid longitudes, from -180 to 360
+    let turns = ((longitudes.normalize(first) - first) / 360.0).round();
+    let shift = [turns, turns - 1.0, turns + 1.0].iter()
+        .map(|turns| turns * 360.0)
+        .find(|shift| first + shift >= -180.0 && last + shift <= 360.0)
+        .unwrap_or(turns * 360.0);
+    if shift == 0.0 {
+        return line.to_vec()
+    }
+    line.iter().map(|p| LatLon { lat: p.lat, lon: p.lon + shift }).collect()
+}
+
+/// Latitude of a line going round the world at a longitude, interpolated linearly.
+fn latitude_at(line: &[LatLon], lon: f64) -> f64 {
+    [0.0, 360.0, -360.0].iter()
+        .find_map(|shift| line.windows(2).find_map(|segment| {
+            let (a, b) = (&segment[0], &segment[1]);
+            let (west, east) = (a.lon + shift, b.lon + shift);
+            if lon < west || lon > east {
+                None
+            } else if west == east {
+                Some(a.lat)
+            } else {
+                Some(a.lat + (lon - west) / (east - west) * (b.lat - a.lat))
+            }
+        }))
+        .unwrap_or(line[0].lat)
+}
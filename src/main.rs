@@ -1,25 +1,53 @@
 #![feature(path_file_prefix)]
 
-use rocket::launch;
+use log::info;
+use rocket::fairing::AdHoc;
 use structopt::StructOpt;
+use crate::namespace::Namespaces;
 use crate::polar::PolarService;
 
-use crate::race::RaceService;
-
 mod api;
+mod bundle;
 mod config;
+mod course;
+mod crossing;
+mod geo;
+mod geojson;
+mod gpx;
+mod history;
+mod kml;
+mod limits;
+mod longitudes;
+mod namespace;
 mod race;
+mod schema;
+mod simplify;
 mod polar;
+mod progress;
+mod spatial;
+mod store;
+mod trash;
+mod validation;
+mod xml;
+mod zone;
 
 #[derive(Debug, StructOpt)]
 struct Cli {
     /// config file
     #[structopt(long = "config-file", short = "c", default_value = "config.yaml")]
     config_file: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Rewrites the races of every namespace stored with an older schema, then exits
+    Migrate,
 }
 
-#[launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
     std::env::var("RUST_LOG").map_err(|_| {
         std::env::set_var("RUST_LOG", "error,races=info");
     }).unwrap_or_default();
@@ -29,9 +57,32 @@ fn rocket() -> _ {
 
     let config: config::Config = confy::load_path(std::path::Path::new(&args.config_file)).unwrap();
 
-    let race_service = RaceService::new(config.races_dir, config.archived_dir);
+    let namespaces = Namespaces::new(&config).unwrap();
+    let names: Vec<String> = namespaces.names().cloned().collect();
+
+    if let Some(Command::Migrate) = args.command {
+        for name in std::iter::once(None).chain(names.iter().map(Some)) {
+            let race_service = namespaces.get(name.map(String::as_str)).unwrap();
+            let count = race_service.migrate().await.unwrap();
+            info!("Migrated {} races of {} to schema version {}", count, name.map_or("the default namespace", String::as_str), schema::VERSION);
+        }
+        return Ok(())
+    }
 
     let polar_service = PolarService::new(config.polars);
 
-    api::init().manage(race_service).manage(polar_service)
+    let purged_races: Vec<_> = namespaces.services().cloned().collect();
+    let retention_days = config.trash.retention_days;
+
+    api::init(&names)
+        .manage(namespaces)
+        .manage(polar_service)
+        .attach(AdHoc::on_liftoff("Trash purge", move |_| Box::pin(async move {
+            for race_service in purged_races {
+                rocket::tokio::spawn(trash::purge_periodically(race_service, retention_days));
+            }
+        })))
+        .launch()
+        .await
+        .map_err(Box::new)
 }
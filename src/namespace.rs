@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+
+use crate::config::{Config, StoreConfig};
+use crate::race::{RaceId, RaceService};
+use crate::store;
+
+/// Path segments of the API that can't be used as namespace names.
+const RESERVED: [&str; 4] = ["races", "legs", "trash", "commits"];
+
+/// Race services of the default catalogue and of each namespace declared in the config, every one
+/// with its own store, so that ids of different namespaces never collide.
+pub(crate) struct Namespaces {
+    default: RaceService,
+    namespaces: HashMap<String, RaceService>,
+}
+
+impl Namespaces {
+
+    pub(crate) fn new(config: &Config) -> Result<Self> {
+        let mut locations = locations(&config.races_dir, &config.archived_dir, &config.store);
+        let mut namespaces = HashMap::new();
+
+        for (name, namespace) in &config.namespaces {
+            // namespaces follow the grammar of race ids, as they are path segments too
+            RaceId::parse(name).map_err(|_| anyhow!("Invalid namespace name {:?}", name))?;
+            if RESERVED.contains(&name.as_str()) {
+                return Err(anyhow!("Namespace name {:?} is reserved", name))
+            }
+            let used = self::locations(&namespace.races_dir, &namespace.archived_dir, &namespace.store);
+            if let Some(location) = used.iter().find(|location| locations.contains(location)) {
+                return Err(anyhow!("{:?} of namespace {} is already used by another namespace", location, name))
+            }
+            locations.extend(used);
+
+            let store = store::init(&namespace.races_dir, &namespace.archived_dir, &namespace.store)?;
+            namespaces.insert(name.clone(), RaceService::new(store, config.longitudes));
+        }
+
+        let store = store::init(&config.races_dir, &config.archived_dir, &config.store)?;
+        Ok(Namespaces { default: RaceService::new(store, config.longitudes), namespaces })
+    }
+
+    /// Race service of the namespace, or of the default catalogue.
+    pub(crate) fn get(&self, namespace: Option<&str>) -> Option<&RaceService> {
+        match namespace {
+            None => Some(&self.default),
+            Some(namespace) => self.namespaces.get(namespace),
+        }
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &String> {
+        self.namespaces.keys()
+    }
+
+    /// Every race service, the default one first.
+    pub(crate) fn services(&self) -> impl Iterator<Item = &RaceService> {
+        std::iter::once(&self.default).chain(self.namespaces.values())
+    }
+}
+
+/// Where the races of a store live, which can't be shared between namespaces: its directories,
+/// or its database unless it is in memory.
+fn locations(races_dir: &str, archived_dir: &str, store: &StoreConfig) -> Vec<PathBuf> {
+    match store {
+        StoreConfig::Sqlite { path } if path == ":memory:" => Vec::new(),
+        StoreConfig::Sqlite { path } => vec![canonical(Path::new(path))],
+        _ => vec![canonical(Path::new(races_dir)), canonical(Path::new(archived_dir))],
+    }
+}
+
+/// Absolute path without symbolic links, `.` or `..`, so that different spellings of a directory
+/// compare equal, even when it doesn't exist yet.
+fn canonical(path: &Path) -> PathBuf {
+    let path = std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                res.pop();
+            },
+            component => res.push(component),
+        }
+        // links are resolved for as long as the path exists
+        if let Ok(existing) = res.canonicalize() {
+            res = existing;
+        }
+    }
+    res
+}
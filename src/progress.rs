@@ -0,0 +1,70 @@
+use crate::course;
+use crate::geo;
+use crate::race::{LatLon, Race};
+
+/// Where a boat stands on the course of a race. Distances are in nautical miles and bearings in
+/// degrees.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    /// number of waypoints passed
+    pub(crate) passed: usize,
+    /// whether `passed` was estimated from the position
+    pub(crate) estimated: bool,
+    /// `None` once every waypoint is passed
+    pub(crate) next: Option<Next>,
+    /// great-circle distance through the marks of the waypoints left
+    pub(crate) distance_to_go: f64,
+}
+
+/// Next waypoint to reach, and how far its mark is.
+#[derive(Debug)]
+pub(crate) struct Next {
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    pub(crate) mark: LatLon,
+    pub(crate) distance: f64,
+    pub(crate) bearing: f64,
+}
+
+/// Progress of a boat at `position` having passed `passed` waypoints, or as many as estimated when
+/// `None`: the leg it is on being the one it makes the smallest detour from. `passed` is at most
+/// the number of waypoints, and waypoints without a mark are skipped, as by `course::course`.
+pub(crate) fn progress(race: &Race, position: &LatLon, passed: Option<usize>) -> Progress {
+    let marks: Vec<(usize, LatLon)> = race.waypoints.iter()
+        .enumerate()
+        .filter_map(|(index, waypoint)| course::mark(waypoint).map(|mark| (index, mark)))
+        .collect();
+
+    let (passed, estimated) = match passed {
+        Some(passed) => (passed, false),
+        None => (estimate(race, &marks, position), true),
+    };
+
+    let left: Vec<&(usize, LatLon)> = marks.iter().filter(|(index, _)| *index >= passed).collect();
+    let next = left.first().map(|(index, mark)| Next {
+        waypoint: *index,
+        name: race.waypoints[*index].name.clone(),
+        mark: mark.clone(),
+        distance: geo::distance(position, mark),
+        bearing: geo::initial_bearing(position, mark),
+    });
+    let distance_to_go = next.as_ref().map_or(0.0, |next| next.distance)
+        + left.windows(2).map(|marks| geo::distance(&marks[0].1, &marks[1].1)).sum::<f64>();
+
+    Progress { passed, estimated, next, distance_to_go }
+}
+
+/// Number of waypoints passed by a boat on the leg it makes the smallest detour from, going
+/// through `position`.
+fn estimate(race: &Race, marks: &[(usize, LatLon)], position: &LatLon) -> usize {
+    let mut from = &race.start;
+    let mut best = (race.waypoints.len(), f64::INFINITY);
+    for (index, mark) in marks {
+        let detour = geo::distance(from, position) + geo::distance(position, mark) - geo::distance(from, mark);
+        if detour < best.1 {
+            best = (*index, detour);
+        }
+        from = mark;
+    }
+    best.0
+}
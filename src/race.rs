@@ -1,80 +1,94 @@
 use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
-use std::ffi::OsStr;
-use std::fs;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use thiserror::Error;
 
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use rocket::request::FromParam;
+use rocket::tokio::sync::{Mutex, MutexGuard};
+use rocket::tokio::task;
+use sha2::{Digest, Sha256};
+
+use crate::bundle;
+use crate::course;
+use crate::course::Course;
+use crate::crossing;
+use crate::crossing::{Passage, TrackPoint};
+use crate::bundle::{ConflictPolicy, ImportResult, ImportStatus};
+use crate::history;
+use crate::history::{Action, Commit, RaceDiff, Revision};
+use crate::limits;
+use crate::limits::PathEvaluation;
+use crate::longitudes;
+use crate::longitudes::Longitudes;
+use crate::progress;
+use crate::progress::Progress;
+use crate::spatial::{Area, SpatialIndex};
+use crate::store::RaceStore;
+use crate::trash::TrashEntry;
+use crate::validation;
+use crate::validation::Violation;
+use crate::zone;
+use crate::zone::Intersection;
+
+#[derive(Clone)]
 pub(crate) struct RaceService {
-    races_dir: PathBuf,
-    archived_dir: PathBuf,
+    store: Arc<dyn RaceStore>,
+    /// serializes the mutations, so that revision checks and writes can't interleave
+    writes: Arc<Mutex<()>>,
+    /// number of mutations done
+    changes: Arc<AtomicU64>,
+    /// range the longitudes of the races written are normalized to
+    longitudes: Longitudes,
+    /// spatial index of the active races and of the archived ones, by whether they are archived
+    spatial: Arc<RwLock<HashMap<bool, Arc<Indexed>>>>,
+}
+
+/// Races along with their spatial index, as of the changes it was built after.
+struct Indexed {
+    /// mutations done and reloads of the store
+    changes: (u64, u64),
+    races: Vec<Race>,
+    index: SpatialIndex,
+}
+
+/// Lock on the mutations, counting one more when released, so that what is computed from the
+/// races is refreshed.
+struct Write<'a> {
+    _lock: MutexGuard<'a, ()>,
+    changes: &'a AtomicU64,
+}
+
+impl Drop for Write<'_> {
+    fn drop(&mut self) {
+        self.changes.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
 impl RaceService {
 
-    fn create_dir(dir: &PathBuf) {
-        if !dir.exists() {
-            if let Err(e) = fs::create_dir_all(&dir) {
-                panic!("Error creating dir {:?} : {}", dir, e);
-            }
-        } else if !dir.is_dir() {
-            panic!("{:?} is not a directory", dir);
+    pub(crate) fn new(store: Arc<dyn RaceStore>, longitudes: Longitudes) -> Self {
+        RaceService {
+            store,
+            writes: Arc::new(Mutex::new(())),
+            changes: Arc::new(AtomicU64::new(0)),
+            longitudes,
+            spatial: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    pub(crate) fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(races_dir: P, archived_dir: Q) -> Self {
-        let races_dir: PathBuf = races_dir.into();
-        let archived_dir: PathBuf = archived_dir.into();
-        Self::create_dir(&races_dir);
-        Self::create_dir(&archived_dir);
-        RaceService { races_dir, archived_dir }
+    async fn write(&self) -> Write<'_> {
+        Write { _lock: self.writes.lock().await, changes: &self.changes }
     }
 
     pub(crate) async fn list(&self, archived: Option<bool>) -> Result<Vec<Race>> {
-        let mut res = Vec::new();
-
-        let (dir, archived) = if let Some(true) = archived {
-            (&self.archived_dir, true)
-        } else {
-            (&self.races_dir, false)
-        };
-
-        let paths = fs::read_dir(dir)?;
-
-        for entry in paths {
-            if let Ok(entry) = entry {
-                if let Ok(metadata) = entry.metadata() {
-                    if metadata.is_file() {
-                        println!("entry {:?}", entry.path());
-                        if let Some(ext) = entry.path().extension() {
-                            if ext == OsStr::new("yaml") {
-                                let file = File::open(entry.path()).unwrap();
-                                let reader = BufReader::new(file);
-
-                                // Read the JSON contents of the file as an instance of `AppInfo`.
-                                match serde_yaml::from_reader(reader) {
-                                    Ok(race) => {
-                                        let mut race: Race = race;
-                                        race.id = Some(entry.path().file_prefix().unwrap().to_string_lossy().to_string());
-                                        race.archived = archived;
-                                        res.push(race);
-                                    },
-                                    Err(e) => {
-                                        println!("Error reading file {:?} : {:?}", entry, e);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    println!("Couldn't get metadata for {:?}", entry.path());
-                }
-            }
-        }
+        let mut res = self.store.list(archived.unwrap_or(false)).await?;
 
         res.sort_by(|a, b| {
             let a = a.start_time.unwrap_or(Utc{}.ymd(1970, 1, 1).and_hms_nano(0, 0, 0, 0));
@@ -84,31 +98,86 @@ impl RaceService {
         Ok(res)
     }
 
-    pub(crate) async fn get(&self, race_id: String) -> Result<Option<Race>> {
+    /// Races, archived or not, whose start, waypoints or course have a point in the area.
+    pub(crate) async fn search(&self, archived: Option<bool>, area: &Area) -> Result<Vec<Race>> {
+        let indexed = self.indexed(archived.unwrap_or(false)).await?;
+        let found = indexed.index.search(area);
+        Ok(indexed.races.iter()
+            .enumerate()
+            .filter(|(index, _)| found.contains(index))
+            .map(|(_, race)| race.clone())
+            .collect())
+    }
 
-        let mut path = self.races_dir.join(format!("{}.yaml", race_id));
-        let mut archived = false;
-        if !path.exists() {
-            path = self.archived_dir.join(format!("{}.yaml", race_id));
-            archived = true;
-            if !path.exists() {
-                return Ok(None)
+    /// Spatial index of the active or archived races, rebuilt when they changed since it was.
+    async fn indexed(&self, archived: bool) -> Result<Arc<Indexed>> {
+        // read before listing the races, so that a change made meanwhile makes the index stale
+        let changes = (self.changes.load(Ordering::SeqCst), self.store.reloads());
+        if let Some(indexed) = self.spatial.read().unwrap().get(&archived) {
+            if indexed.changes == changes {
+                return Ok(indexed.clone())
             }
         }
 
-        let reader = BufReader::new(File::open(&path)?);
+        let races = self.list(Some(archived)).await?;
+        let index = SpatialIndex::new(&races);
+        let indexed = Arc::new(Indexed { changes, races, index });
+        self.spatial.write().unwrap().insert(archived, indexed.clone());
+        Ok(indexed)
+    }
 
-        // Read the JSON contents of the file as an instance of `AppInfo`.
-        let race: Option<Race> = serde_yaml::from_reader(reader)?;
-        let race = race.map(|mut r: Race| {
-            r.id = Some(race_id);
-            r.archived = archived;
-            r
-        });
-        Ok(race)
+    pub(crate) async fn get(&self, race_id: RaceId) -> Result<Option<Race>> {
+        self.store.get(&race_id).await
+    }
+
+    /// Course of the race, with a polyline whose points are at most `step` nautical miles apart.
+    pub(crate) async fn course(&self, race_id: RaceId, step: f64) -> Result<Option<Course>> {
+        Ok(self.store.get(&race_id).await?.map(|race| course::course(&race, step)))
+    }
+
+    /// Zones to avoid that the position `from` is in, or that the segment from `from` to `to`
+    /// crosses.
+    pub(crate) async fn intersections(&self, race_id: RaceId, from: LatLon, to: Option<LatLon>) -> Result<Option<Vec<Intersection>>> {
+        Ok(self.store.get(&race_id).await?.map(|race| zone::intersections(&race, &from, to.as_ref())))
+    }
+
+    /// Waypoints of a race that a track passed, and those it missed.
+    pub(crate) async fn passage(&self, race_id: RaceId, track: Vec<TrackPoint>) -> Result<Option<Passage>> {
+        match self.store.get(&race_id).await? {
+            Some(race) => Ok(Some(crossing::passage(&race, &track)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Progress of a boat at `position` along the course of a race, having passed `passed` of its
+    /// waypoints, or as many as estimated.
+    pub(crate) async fn progress(&self, race_id: RaceId, position: LatLon, passed: Option<usize>) -> Result<Option<Progress>> {
+        match self.store.get(&race_id).await? {
+            Some(race) => {
+                if let Some(passed) = passed.filter(|passed| *passed > race.waypoints.len()) {
+                    return Err(RaceError::WaypointNotFound(race_id.to_string(), passed).into())
+                }
+                Ok(Some(progress::progress(&race, &position, passed)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Evaluates a path against the ice limits of a race, the parallels at the poles bounding those
+    /// of races without any.
+    pub(crate) async fn evaluate_limits(&self, race_id: RaceId, path: Vec<LatLon>) -> Result<Option<PathEvaluation>> {
+        Ok(self.store.get(&race_id).await?.map(|race| {
+            let limits = race.ice_limits.unwrap_or(Limits { north: Vec::new(), south: Vec::new(), max_lat: 90.0, min_lat: -90.0 });
+            limits::evaluate_path(&limits, &path)
+        }))
     }
 
-    fn get_id(&self, race: &Race) -> Result<String> {
+    fn normalize(&self, mut race: Race) -> Race {
+        longitudes::normalize(&mut race, self.longitudes);
+        race
+    }
+
+    fn get_id(&self, race: &Race) -> Result<RaceId> {
         match &race.id {
             Some(id) => {
                 Ok(id.clone())
@@ -119,114 +188,271 @@ impl RaceService {
         }
     }
 
-    pub(crate) async fn create(&self, race: &Race) -> Result<()> {
-        let id = self.get_id(race)?;
-        let path = self.races_dir.join(format!("{}.yaml", id));
-        if path.exists() {
-            Err(RaceError::AlreadyExists(id).into())
-        } else {
-            match self.save_race(&path, race) {
-                Ok(()) => Ok(()),
-                Err(e) => {
-                    println!("Error saving race {:?} : {}", path, e);
-                    Err(e.into())
-                }
+    /// Returns the race if it exists and its current revision is one of `expected`, if any.
+    async fn check_revision(&self, race_id: &RaceId, archived: Option<bool>, expected: Option<&[String]>) -> Result<Race> {
+        let race = match self.store.get(race_id).await? {
+            Some(race) if archived.is_none_or(|archived| race.archived == archived) => race,
+            _ => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        if let Some(expected) = expected {
+            if !expected.contains(&race.revision()?) {
+                return Err(RaceError::RevisionMismatch(race_id.to_string()).into())
             }
         }
+        Ok(race)
     }
 
-    pub(crate) async fn update(&self, race_id: String, race: &Race) -> Result<()> {
-        let mut path = self.races_dir.join(format!("{}.yaml", race_id));
-        if !path.exists() {
-            return Err(RaceError::NotFound(race_id).into())
+    /// Appends a new revision to the history of the race.
+    async fn record(&self, race_id: &RaceId, action: Action, race: &Race, author: Option<String>) -> Result<()> {
+        let number = self.store.revisions(race_id).await?.last().map_or(1, |r| r.number + 1);
+        let revision = Revision {
+            number,
+            timestamp: Utc::now(),
+            author,
+            action,
+            race: race.clone(),
+        };
+        self.store.add_revision(race_id, &revision).await
+    }
+
+    fn validate(&self, race: &Race) -> Result<()> {
+        let violations = validation::validate(race);
+        if violations.is_empty() {
+            Ok(())
         } else {
+            Err(RaceError::Invalid(violations).into())
+        }
+    }
 
-            if let Some(id) = &race.id {
-                if id != &race_id {
-                    // the id change. must remove old file and create new one.
-                    match fs::remove_file(&path) {
-                        Ok(_) => {},
-                        Err(e) => {
-                            println!("Error removing file {:?} : {}", path, e);
-                            return Err(e.into());
-                        }
-                    }
-                    path = self.races_dir.join(format!("{}.yaml", id))
-                }
-            }
+    pub(crate) async fn create(&self, race: &Race, author: Option<String>) -> Result<()> {
+        let id = self.get_id(race)?;
+        let race = &self.normalize(race.clone());
+        self.validate(race)?;
+        let _lock = self.write().await;
+        self.store.create(&id, race).await?;
+        self.record(&id, Action::Create, race, author).await
+    }
 
-            match self.save_race(&path, race) {
-                Ok(()) => Ok(()),
-                Err(e) => {
-                    println!("Error saving race {:?} : {}", path, e);
-                    Err(e.into())
-                }
-            }
+    /// Replaces the race, returning it as stored.
+    pub(crate) async fn update(&self, race_id: RaceId, race: &Race, expected: Option<&[String]>, author: Option<String>) -> Result<Race> {
+        let new_id = race.id.clone().unwrap_or_else(|| race_id.clone());
+        let race = self.normalize(race.clone());
+        self.validate(&race)?;
+        let _lock = self.write().await;
+        self.check_revision(&race_id, Some(false), expected).await?;
+        self.store.update(&race_id, &new_id, &race).await?;
+        self.record(&new_id, Action::Update, &race, author).await?;
+        Ok(race)
+    }
+
+    /// Replaces the waypoints of the race, returning the updated race.
+    pub(crate) async fn update_waypoints(&self, race_id: RaceId, waypoints: Vec<Waypoint>, expected: Option<&[String]>, author: Option<String>) -> Result<Race> {
+        let _lock = self.write().await;
+        let mut race = self.check_revision(&race_id, Some(false), expected).await?;
+        race.waypoints = waypoints;
+        let race = self.normalize(race);
+        self.validate(&race)?;
+        self.store.update(&race_id, &race_id, &race).await?;
+        self.record(&race_id, Action::Update, &race, author).await?;
+        Ok(race)
+    }
+
+    /// Moves the race to the trash.
+    pub(crate) async fn delete(&self, race_id: RaceId, expected: Option<&[String]>, author: Option<String>) -> Result<()> {
+        let _lock = self.write().await;
+        let race = self.check_revision(&race_id, None, expected).await?;
+        self.store.delete(&race_id).await?;
+        self.record(&race_id, Action::Delete, &race, author).await
+    }
+
+    pub(crate) async fn archive(&self, race_id: RaceId, expected: Option<&[String]>, author: Option<String>) -> Result<()> {
+        let _lock = self.write().await;
+        let race = self.check_revision(&race_id, Some(false), expected).await?;
+        self.store.archive(&race_id).await?;
+        self.record(&race_id, Action::Archive, &race, author).await
+    }
+
+    pub(crate) async fn restore(&self, race_id: RaceId, author: Option<String>) -> Result<()> {
+        let _lock = self.write().await;
+        self.store.restore(&race_id).await?;
+        if let Some(race) = self.store.get(&race_id).await? {
+            self.record(&race_id, Action::Restore, &race, author).await?;
         }
+        Ok(())
     }
 
-    pub(crate) async fn delete(&self, race_id: String) -> Result<()> {
-        let mut path = self.races_dir.join(format!("{}.yaml", race_id));
-        if !path.exists() {
-            path = self.archived_dir.join(format!("{}.yaml", race_id));
-            if !path.exists() {
-                return Err(RaceError::NotFound(race_id).into())
-            }
+    pub(crate) async fn trash(&self) -> Result<Vec<TrashEntry>> {
+        let mut res = self.store.trash().await?;
+        res.sort_by_key(|entry| Reverse(entry.deleted_at));
+        Ok(res)
+    }
+
+    pub(crate) async fn untrash(&self, race_id: RaceId, author: Option<String>) -> Result<()> {
+        let _lock = self.write().await;
+        self.store.untrash(&race_id).await?;
+        if let Some(race) = self.store.get(&race_id).await? {
+            self.record(&race_id, Action::Restore, &race, author).await?;
         }
+        Ok(())
+    }
 
-        match fs::remove_file(&path) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                println!("Error removing file {:?} : {}", path, e);
-                Err(e.into())
+    pub(crate) async fn purge(&self, race_id: RaceId) -> Result<()> {
+        let _lock = self.write().await;
+        self.store.purge(&race_id).await
+    }
+
+    /// Purges the races deleted before `before`, or all of them. Returns the number of purged races.
+    pub(crate) async fn purge_trash(&self, before: Option<DateTime<Utc>>) -> Result<usize> {
+        let _lock = self.write().await;
+        let mut count = 0;
+        for entry in self.store.trash().await? {
+            if before.is_none_or(|before| entry.deleted_at < before) {
+                if let Some(race_id) = &entry.race.id {
+                    self.store.purge(race_id).await?;
+                    count += 1;
+                }
             }
         }
+        Ok(count)
     }
 
-    pub(crate) async fn archive(&self, race_id: String) -> Result<()> {
-        let path = self.races_dir.join(format!("{}.yaml", race_id));
-        if !path.exists() {
-            Err(RaceError::NotFound(race_id).into())
-        } else {
-            let archived = self.archived_dir.join(format!("{}.yaml", race_id));
-            Self::rename(&path, &archived)
+    pub(crate) async fn revisions(&self, race_id: RaceId) -> Result<Vec<Revision>> {
+        let revisions = self.store.revisions(&race_id).await?;
+        if revisions.is_empty() && self.store.get(&race_id).await?.is_none() {
+            return Err(RaceError::NotFound(race_id.to_string()).into())
         }
+        Ok(revisions)
     }
 
-    pub(crate) async fn restore(&self, race_id: String) -> Result<()> {
-        let archived = self.archived_dir.join(format!("{}.yaml", race_id));
-        if !archived.exists() {
-            Err(RaceError::NotFound(race_id).into())
-        } else {
-            let path = self.races_dir.join(format!("{}.yaml", race_id));
-            if path.exists() {
-                Err(RaceError::AlreadyExists(race_id).into())
-            } else {
-                Self::rename(&archived, &path)
-            }
+    pub(crate) async fn revision(&self, race_id: RaceId, number: u32) -> Result<Revision> {
+        self.revisions(race_id.clone()).await?
+            .into_iter()
+            .find(|r| r.number == number)
+            .ok_or_else(|| RaceError::RevisionNotFound(race_id.to_string(), number).into())
+    }
+
+    pub(crate) async fn diff(&self, race_id: RaceId, from: u32, to: u32) -> Result<RaceDiff> {
+        let revisions = self.revisions(race_id.clone()).await?;
+        let find = |number: u32| revisions.iter()
+            .find(|r| r.number == number)
+            .ok_or_else(|| RaceError::RevisionNotFound(race_id.to_string(), number));
+        Ok(history::diff(&find(from)?.race, &find(to)?.race))
+    }
+
+    /// Commits of the race, or of the whole catalogue, when the store is versioned.
+    pub(crate) async fn commits(&self, race_id: Option<RaceId>) -> Result<Option<Vec<Commit>>> {
+        self.store.commits(race_id.as_ref()).await
+    }
+
+    /// Replaces the active race by the content of one of its revisions, returning the restored race.
+    /// The revision goes through the same normalization and validation as an update, as it may
+    /// predate them.
+    pub(crate) async fn rollback(&self, race_id: RaceId, number: u32, expected: Option<&[String]>, author: Option<String>) -> Result<Race> {
+        let revision = self.revision(race_id.clone(), number).await?;
+        let race = self.normalize(revision.race);
+        self.validate(&race)?;
+        let _lock = self.write().await;
+        self.check_revision(&race_id, Some(false), expected).await?;
+        self.store.update(&race_id, &race_id, &race).await?;
+        self.record(&race_id, Action::Rollback, &race, author).await?;
+        Ok(race)
+    }
+
+    /// Rewrites the races stored with an older schema. Returns their number.
+    pub(crate) async fn migrate(&self) -> Result<usize> {
+        let _lock = self.write().await;
+        self.store.migrate().await
+    }
+
+    /// Bundle of all the races, active and archived.
+    pub(crate) async fn export(&self) -> Result<Vec<u8>> {
+        let mut races = self.store.list(false).await?;
+        races.extend(self.store.list(true).await?);
+        races.sort_by(|a, b| a.id.cmp(&b.id));
+        task::spawn_blocking(move || bundle::write(&races)).await?
+    }
+
+    /// Imports the races of a bundle, resolving id conflicts according to `policy`. A race which
+    /// can't be imported is reported as failed without stopping the import.
+    pub(crate) async fn import(&self, bundle: Vec<u8>, policy: ConflictPolicy, author: Option<String>) -> Result<Vec<ImportResult>> {
+        let races = task::spawn_blocking(move || bundle::read(&bundle)).await??;
+        let _lock = self.write().await;
+        let mut res = Vec::new();
+        for (race_id, race) in races {
+            let status = match race {
+                Ok(race) => self.import_race(&race_id, race, policy, author.clone()).await
+                    .unwrap_or_else(|e| ImportStatus::Failed(e.to_string())),
+                Err(e) => ImportStatus::Failed(e.to_string()),
+            };
+            res.push(ImportResult { id: race_id, status });
         }
+        Ok(res)
     }
 
-    fn rename(from: &Path, to: &Path) -> Result<()> {
-        match fs::rename(from, to) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                println!("Error moving file {:?} to {:?} : {}", from, to, e);
-                Err(e.into())
+    async fn import_race(&self, race_id: &RaceId, race: Race, policy: ConflictPolicy, author: Option<String>) -> Result<ImportStatus> {
+        let mut race = self.normalize(race);
+        self.validate(&race)?;
+        let archived = race.archived;
+        race.archived = false;
+
+        let (race_id, status) = match self.store.create(race_id, &race).await {
+            Ok(()) => (race_id.clone(), ImportStatus::Created),
+            // a race in the trash conflicts as much as an active or archived one
+            Err(e) => match (e.downcast_ref::<RaceError>(), policy) {
+                (Some(RaceError::AlreadyExists(_) | RaceError::InTrash(_)), ConflictPolicy::Skip) => return Ok(ImportStatus::Skipped),
+                (Some(RaceError::AlreadyExists(_) | RaceError::InTrash(_)), ConflictPolicy::Overwrite) => {
+                    self.overwrite(race_id, &race).await?;
+                    (race_id.clone(), ImportStatus::Overwritten)
+                },
+                (Some(RaceError::AlreadyExists(_) | RaceError::InTrash(_)), ConflictPolicy::Rename) => {
+                    let new_id = self.create_renamed(race_id, &race).await?;
+                    (new_id.clone(), ImportStatus::Renamed(new_id))
+                },
+                _ => return Err(e),
             }
+        };
+        race.id = Some(race_id.clone());
+
+        let action = match status {
+            ImportStatus::Overwritten => Action::Update,
+            _ => Action::Create,
+        };
+        self.record(&race_id, action, &race, author.clone()).await?;
+
+        if archived {
+            self.store.archive(&race_id).await?;
+            self.record(&race_id, Action::Archive, &race, author).await?;
         }
+        Ok(status)
     }
 
-    fn save_race(&self, path: &Path, race: &Race) -> Result<()> {
-
-        let f = std::fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)?;
-        serde_yaml::to_writer(f, race)?;
+    /// Replaces an existing race, bringing it back from the archive or the trash if needed.
+    async fn overwrite(&self, race_id: &RaceId, race: &Race) -> Result<()> {
+        if self.store.get(race_id).await?.is_none() {
+            self.store.untrash(race_id).await?;
+        }
+        if self.store.get(race_id).await?.is_some_and(|r| r.archived) {
+            self.store.restore(race_id).await?;
+        }
+        self.store.update(race_id, race_id, race).await
+    }
 
-        Ok(())
+    /// Creates the race under the first free id made of `race_id` and a numeric suffix.
+    async fn create_renamed(&self, race_id: &RaceId, race: &Race) -> Result<RaceId> {
+        let mut n = 2;
+        loop {
+            let suffix = format!("-{}", n);
+            let base = &race_id.as_str()[..race_id.as_str().len().min(RaceId::MAX_LEN - suffix.len())];
+            let new_id = RaceId::parse(&format!("{}{}", base, suffix))?;
+            match self.store.create(&new_id, race).await {
+                Ok(()) => return Ok(new_id),
+                Err(e) => match e.downcast_ref::<RaceError>() {
+                    Some(RaceError::AlreadyExists(_) | RaceError::InTrash(_)) => n += 1,
+                    _ => return Err(e),
+                }
+            }
+        }
     }
 }
 
@@ -236,15 +462,89 @@ pub enum RaceError {
     AlreadyExists(String),
     #[error("Race {0} does not exist.")]
     NotFound(String),
+    #[error("Race {0} is in the trash : restore it with POST /trash/{0}/restore, or purge it with DELETE /trash/{0}.")]
+    InTrash(String),
     #[error("Id is mandatory")]
     IdIsMandatory(),
+    #[error("Invalid id {0:?} : expected up to {} lowercase letters, digits, '-' or '_', starting with a letter or a digit.", RaceId::MAX_LEN)]
+    InvalidId(String),
+    #[error("Race {0} has been modified.")]
+    RevisionMismatch(String),
+    #[error("Race {0} has no revision {1}.")]
+    RevisionNotFound(String, u32),
+    #[error("Race {0} is stored in several files : {1}.")]
+    DuplicateFiles(String, String),
+    #[error("Invalid race : {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Invalid(Vec<Violation>),
+    #[error("Invalid bundle : {0}")]
+    InvalidBundle(String),
+    #[error("Bundle too large : {0}")]
+    BundleTooLarge(String),
+    #[error("Invalid GPX : {0}")]
+    InvalidGpx(String),
+    #[error("Invalid track : {0}")]
+    InvalidTrack(String),
+    #[error("Race {0} has fewer than {1} waypoints.")]
+    WaypointNotFound(String, usize),
 }
 
+/// Identifier of a race, also used as its file name: a short slug that can't escape the data
+/// directories.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct RaceId(String);
+
+impl RaceId {
+    pub(crate) const MAX_LEN: usize = 64;
+
+    pub(crate) fn parse(id: &str) -> Result<Self, RaceError> {
+        let valid = id.len() <= Self::MAX_LEN
+            && id.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+            && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if valid {
+            Ok(RaceId(id.to_string()))
+        } else {
+            Err(RaceError::InvalidId(id.to_string()))
+        }
+    }
 
-#[derive(Deserialize, Serialize, Debug)]
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for RaceId {
+    type Error = RaceError;
+
+    fn try_from(id: String) -> Result<Self, Self::Error> {
+        RaceId::parse(&id)
+    }
+}
+
+impl From<RaceId> for String {
+    fn from(id: RaceId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for RaceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'a> FromParam<'a> for RaceId {
+    type Error = RaceError;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        RaceId::parse(param)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub(crate) struct Race {
     #[serde(skip_serializing)]
-    pub(crate) id: Option<String>,
+    pub(crate) id: Option<RaceId>,
     pub(crate) race_id: Option<String>,
     #[serde(default, skip_serializing)]
     pub(crate) archived: bool,
@@ -260,13 +560,21 @@ pub(crate) struct Race {
     pub(crate) ice_limits: Option<Limits>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl Race {
+    /// Hash of the stored content of the race, used as its `ETag`.
+    pub(crate) fn revision(&self) -> Result<String> {
+        let content = serde_json::to_vec(self)?;
+        Ok(format!("{:x}", Sha256::digest(&content)))
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub(crate) struct LatLon {
     pub(crate) lat: f64,
     pub(crate) lon: f64,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub(crate) struct Limits {
     pub(crate) north: Vec<LatLon>,
     pub(crate) south: Vec<LatLon>,
@@ -276,15 +584,92 @@ pub(crate) struct Limits {
     pub(crate) min_lat: f64,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub(crate) struct Waypoint {
     pub(crate) name: String,
+    pub(crate) kind: WaypointKind,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) radius: Option<u8>,
+    /// points of a line, crossed leaving the first one to port, or center of a circle
     pub(crate) latlons: Vec<LatLon>,
+    /// side the mark is left on, the mark being the first point of the line to port, and the
+    /// second one to starboard
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) side: Option<Side>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) group: Option<u8>,
+    /// whether engines are allowed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) engine: Option<bool>,
+    /// checkpoint of the game the waypoint was imported from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) checkpoint: Option<Checkpoint>,
     #[serde(rename = "toAvoid", skip_serializing_if = "Option::is_none")]
-    pub(crate) to_avoid: Option<Vec<Vec<Vec<f64>>>>
+    pub(crate) to_avoid: Option<MultiPolygon>
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum WaypointKind {
+    /// line between two points
+    Gate,
+    /// mark to round, with the line it is passed through, or circle to reach
+    Buoy,
+    /// circle ending the race
+    Finish,
+}
+
+impl WaypointKind {
+    /// Kind of a waypoint that doesn't tell it: a gate without radius, a buoy with one, unless it
+    /// is the last waypoint, the finish.
+    pub(crate) fn infer(radius: Option<u8>, last: bool) -> Self {
+        match (radius, last) {
+            (None, _) => WaypointKind::Gate,
+            (Some(_), false) => WaypointKind::Buoy,
+            (Some(_), true) => WaypointKind::Finish,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            WaypointKind::Gate => "gate",
+            WaypointKind::Buoy => "buoy",
+            WaypointKind::Finish => "finish",
+        }
+    }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Side {
+    Port,
+    Stbd,
+}
 
+impl Side {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Side::Port => "port",
+            Side::Stbd => "stbd",
+        }
+    }
+}
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Checkpoint {
+    pub(crate) id: u8,
+    pub(crate) name: String,
+}
+
+/// Zones to avoid around a waypoint.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct MultiPolygon(pub(crate) Vec<Polygon>);
+
+/// Area inside `exterior` and outside its `holes`, every ring being closed: its last point is its
+/// first one.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct Polygon {
+    pub(crate) exterior: Vec<LatLon>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) holes: Vec<Vec<LatLon>>,
+}
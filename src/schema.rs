@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use serde::de::Error as _;
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+use crate::race::Race;
+
+/// Version of the stored race documents, written in their `schemaVersion` field.
+pub(crate) const VERSION: u32 = 3;
+
+const VERSION_FIELD: &str = "schemaVersion";
+
+/// Upgrades a document from the version of its index to the next one.
+type Migration = fn(&mut Map<String, Value>);
+
+/// Migrations, in order: documents without `schemaVersion` are at version 0.
+const MIGRATIONS: [Migration; VERSION as usize] = [
+    v0_to_v1,
+    v1_to_v2,
+    v2_to_v3,
+];
+
+/// A race as stored, along with the version of its schema.
+#[derive(Serialize)]
+pub(crate) struct Versioned<'a> {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    #[serde(flatten)]
+    race: &'a Race,
+}
+
+impl<'a> Versioned<'a> {
+    pub(crate) fn new(race: &'a Race) -> Self {
+        Versioned { schema_version: VERSION, race }
+    }
+}
+
+/// Version of a stored document.
+pub(crate) fn version(document: &Value) -> u32 {
+    document.get(VERSION_FIELD).and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+/// Reads a stored race, applying the migrations it misses.
+pub(crate) fn upgrade(mut document: Value) -> Result<Race> {
+    let version = version(&document);
+    if version > VERSION {
+        return Err(anyhow!("Unsupported schema version {}, latest is {}", version, VERSION))
+    }
+
+    let fields = document.as_object_mut().ok_or_else(|| anyhow!("A race must be a map"))?;
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(fields);
+    }
+    fields.remove(VERSION_FIELD);
+
+    Ok(serde_json::from_value(document)?)
+}
+
+/// Serializes a race embedded in another document, for `#[serde(with = "crate::schema")]`.
+pub(crate) fn serialize<S: Serializer>(race: &Race, serializer: S) -> Result<S::Ok, S::Error> {
+    Versioned::new(race).serialize(serializer).map_err(S::Error::custom)
+}
+
+/// Deserializes a race embedded in another document, for `#[serde(with = "crate::schema")]`.
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Race, D::Error> {
+    upgrade(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+fn rename(fields: &mut Map<String, Value>, from: &str, to: &str) {
+    if let Some(value) = fields.remove(from) {
+        fields.entry(to).or_insert(value);
+    }
+}
+
+/// Unversioned documents were written by hand or by older versions, with both snake case and
+/// camel case keys, and without the fields that were added since.
+fn v0_to_v1(race: &mut Map<String, Value>) {
+    rename(race, "raceId", "race_id");
+    rename(race, "short_name", "shortName");
+    rename(race, "startTime", "start_time");
+    rename(race, "endTime", "end_time");
+    rename(race, "iceLimits", "ice_limits");
+    race.entry("boat").or_insert_with(|| Value::String(String::new()));
+    race.entry("waypoints").or_insert_with(|| Value::Array(Vec::new()));
+
+    if let Some(Value::Object(limits)) = race.get_mut("ice_limits") {
+        rename(limits, "max_lat", "maxLat");
+        rename(limits, "min_lat", "minLat");
+    }
+    if let Some(Value::Array(waypoints)) = race.get_mut("waypoints") {
+        for waypoint in waypoints.iter_mut().filter_map(Value::as_object_mut) {
+            rename(waypoint, "to_avoid", "toAvoid");
+        }
+    }
+}
+
+/// Zones to avoid were open rings of `[lat, lon]` arrays, latitude first as the clients wrote
+/// them (not the `[lon, lat]` order of GeoJSON); they are polygons of `{lat, lon}` points which
+/// can have holes, with closed rings.
+fn v1_to_v2(race: &mut Map<String, Value>) {
+    if let Some(Value::Array(waypoints)) = race.get_mut("waypoints") {
+        for waypoint in waypoints.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(Value::Array(rings)) = waypoint.get_mut("toAvoid") {
+                for ring in rings.iter_mut() {
+                    if let Value::Array(points) = ring {
+                        let mut exterior: Vec<Value> = points.iter()
+                            .map(|point| match point.as_array().map(Vec::as_slice) {
+                                Some([lat, lon]) => json!({ "lat": lat, "lon": lon }),
+                                _ => point.clone(),
+                            })
+                            .collect();
+                        if let (Some(first), Some(last)) = (exterior.first(), exterior.last()) {
+                            if first != last {
+                                exterior.push(first.clone());
+                            }
+                        }
+                        *ring = json!({ "exterior": exterior });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Waypoints have a kind, inferred as `WaypointKind::infer` does: gates have no radius, and the
+/// last circle of a course is its finish.
+fn v2_to_v3(race: &mut Map<String, Value>) {
+    if let Some(Value::Array(waypoints)) = race.get_mut("waypoints") {
+        let count = waypoints.len();
+        for (index, waypoint) in waypoints.iter_mut().enumerate() {
+            if let Value::Object(waypoint) = waypoint {
+                let radius = waypoint.get("radius").is_some_and(|radius| !radius.is_null());
+                let kind = match (radius, index + 1 == count) {
+                    (false, _) => "gate",
+                    (true, false) => "buoy",
+                    (true, true) => "finish",
+                };
+                waypoint.entry("kind").or_insert_with(|| Value::String(kind.to_string()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::race::{LatLon, Race, RaceId, Side, Waypoint, WaypointKind};
+    use crate::validation::validate;
+
+    use super::{upgrade, Versioned, VERSION};
+
+    #[test]
+    fn upgrades_an_unversioned_race() {
+        let document = json!({
+            "raceId": "vg2020",
+            "name": "Vendée Globe",
+            "startTime": "2020-11-08T12:02:00Z",
+            "start": { "lat": 46.47, "lon": -1.79 },
+            "waypoints": [
+                { "name": "1", "latlons": [{ "lat": 40.0, "lon": -10.0 }, { "lat": 40.0, "lon": -11.0 }], "side": "stbd" },
+                {
+                    "name": "2",
+                    "radius": 5,
+                    "latlons": [{ "lat": -40.0, "lon": 20.0 }],
+                    "to_avoid": [[[-41.0, 19.0], [-41.0, 21.0], [-42.0, 21.0], [-42.0, 19.0]]],
+                },
+                { "name": "end", "radius": 1, "latlons": [{ "lat": 46.47, "lon": -1.79 }] },
+            ],
+            "iceLimits": {
+                "north": [{ "lat": -40.0, "lon": -180.0 }, { "lat": -40.0, "lon": 180.0 }],
+                "south": [{ "lat": -60.0, "lon": -180.0 }, { "lat": -60.0, "lon": 180.0 }],
+                "max_lat": 80.0,
+                "min_lat": -60.0,
+            },
+        });
+
+        let race = upgrade(document).unwrap();
+
+        assert_eq!(race.race_id.as_deref(), Some("vg2020"));
+        assert_eq!(race.boat, "");
+        assert!(race.start_time.is_some());
+        let kinds: Vec<WaypointKind> = race.waypoints.iter().map(|w| w.kind).collect();
+        assert_eq!(kinds, [WaypointKind::Gate, WaypointKind::Buoy, WaypointKind::Finish]);
+        assert_eq!(race.waypoints[0].side, Some(Side::Stbd));
+
+        // rings were [lat, lon], and left open
+        let zone = &race.waypoints[1].to_avoid.as_ref().unwrap().0[0];
+        let exterior: Vec<(f64, f64)> = zone.exterior.iter().map(|p| (p.lat, p.lon)).collect();
+        assert_eq!(exterior, [(-41.0, 19.0), (-41.0, 21.0), (-42.0, 21.0), (-42.0, 19.0), (-41.0, 19.0)]);
+        assert!(validate(&race).is_empty());
+
+        let limits = race.ice_limits.unwrap();
+        assert_eq!((limits.max_lat, limits.min_lat), (80.0, -60.0));
+    }
+
+    #[test]
+    fn keeps_closed_rings_and_explicit_kinds() {
+        let document = json!({
+            "schemaVersion": 1,
+            "race_id": null,
+            "name": "Test",
+            "boat": "imoca",
+            "start": { "lat": 0.0, "lon": 0.0 },
+            "waypoints": [{
+                "name": "1",
+                "kind": "buoy",
+                "radius": 1,
+                "latlons": [{ "lat": 1.0, "lon": 1.0 }],
+                "toAvoid": [[[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [0.0, 0.0]]],
+            }],
+        });
+
+        let race = upgrade(document).unwrap();
+
+        assert_eq!(race.waypoints[0].kind, WaypointKind::Buoy);
+        assert_eq!(race.waypoints[0].to_avoid.as_ref().unwrap().0[0].exterior.len(), 4);
+    }
+
+    #[test]
+    fn reads_back_the_races_it_writes() {
+        let race = Race {
+            id: Some(RaceId::parse("test").unwrap()),
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: LatLon { lat: 46.5, lon: -1.8 },
+            waypoints: vec![Waypoint {
+                name: String::from("end"),
+                kind: WaypointKind::Finish,
+                radius: Some(1),
+                latlons: vec![LatLon { lat: 46.4, lon: -1.9 }],
+                side: Some(Side::Stbd),
+                group: Some(1),
+                engine: Some(false),
+                checkpoint: None,
+                to_avoid: None,
+            }],
+            ice_limits: None,
+        };
+
+        let document = serde_json::to_value(Versioned::new(&race)).unwrap();
+        assert_eq!(document["schemaVersion"], VERSION);
+
+        assert_eq!(upgrade(document).unwrap().revision().unwrap(), race.revision().unwrap());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        assert!(upgrade(json!({ "schemaVersion": VERSION + 1 })).is_err());
+    }
+}
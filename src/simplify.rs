@@ -0,0 +1,242 @@
+use std::collections::BTreeSet;
+
+use crate::geo;
+use crate::race::{LatLon, Limits, Polygon, Race};
+
+/// How far a point is from the chord from `a` to `b` replacing it, in nautical miles, infinite
+/// when it can't be dropped.
+type Deviation = dyn Fn(&LatLon, &LatLon, &LatLon) -> f64;
+
+/// Simplifies the ice limits and the zones to avoid of a race, with Douglas-Peucker: points less
+/// than `tolerance` nautical miles from the chord replacing them are dropped. Ice limits never
+/// become more permissive, the north one never going north of the original, and the south one
+/// never south of it. Simplified lines and rings don't cross where the originals didn't: the
+/// limits one another, and the rings of a zone one another and themselves.
+pub(crate) fn simplify(race: &mut Race, tolerance: f64) {
+    if let Some(limits) = &mut race.ice_limits {
+        simplify_limits(limits, tolerance);
+    }
+    for zones in race.waypoints.iter_mut().filter_map(|w| w.to_avoid.as_mut()) {
+        for polygon in &mut zones.0 {
+            simplify_polygon(polygon, tolerance);
+        }
+    }
+}
+
+fn simplify_limits(limits: &mut Limits, tolerance: f64) {
+    // the longitudes of the limits are sorted, so that the latitude of a chord is known at each
+    // point it replaces
+    let north = |v: &LatLon, a: &LatLon, b: &LatLon| vertical(v, a, b, 1.0);
+    let south = |v: &LatLon, a: &LatLon, b: &LatLon| vertical(v, a, b, -1.0);
+    let lines = [limits.north.clone(), limits.south.clone()];
+    let deviations: [&Deviation; 2] = [&north, &south];
+
+    let kept = simplify_lines(&lines, &deviations, tolerance, 2);
+    limits.north = select(&lines[0], &kept[0]);
+    limits.south = select(&lines[1], &kept[1]);
+}
+
+fn simplify_polygon(polygon: &mut Polygon, tolerance: f64) {
+    let perpendicular = |v: &LatLon, a: &LatLon, b: &LatLon| geo::distance_to_line(v, &[a.clone(), b.clone()]).unwrap_or(0.0);
+    // computations are made on continuous longitudes, and the original points are kept
+    let rings = geo::unwrap_polygon(polygon);
+    let deviations: Vec<&Deviation> = rings.iter().map(|_| &perpendicular as &Deviation).collect();
+
+    // a ring keeps at least three points, and its closing one
+    let kept = simplify_lines(&rings, &deviations, tolerance, 4);
+    polygon.exterior = select(&polygon.exterior, &kept[0]);
+    polygon.holes = polygon.holes.iter().zip(&kept[1..]).map(|(hole, kept)| select(hole, kept)).collect();
+}
+
+/// Indices of the points kept of each line, at least `minimum` of them, and chords crossing one
+/// another being split until they don't.
+fn simplify_lines(lines: &[Vec<LatLon>], deviations: &[&Deviation], tolerance: f64, minimum: usize) -> Vec<BTreeSet<usize>> {
+    let mut kept: Vec<BTreeSet<usize>> = lines.iter().zip(deviations)
+        .map(|(line, deviation)| {
+            let mut kept = BTreeSet::new();
+            if !line.is_empty() {
+                kept.extend([0, line.len() - 1]);
+                douglas_peucker(line, 0, line.len() - 1, tolerance, *deviation, &mut kept);
+                while kept.len() < minimum.min(line.len()) {
+                    let (first, last) = chords(&kept).into_iter()
+                        .max_by(|a, b| farthest(line, a.0, a.1, *deviation).1.total_cmp(&farthest(line, b.0, b.1, *deviation).1))
+                        .unwrap_or((0, 0));
+                    kept.insert(farthest(line, first, last, *deviation).0);
+                }
+            }
+            kept
+        })
+        .collect();
+
+    while let Some((line, chord)) = crossing(lines, &kept) {
+        kept[line].insert(farthest(&lines[line], chord.0, chord.1, deviations[line]).0);
+    }
+    kept
+}
+
+fn douglas_peucker(line: &[LatLon], first: usize, last: usize, tolerance: f64, deviation: &Deviation, kept: &mut BTreeSet<usize>) {
+    if last <= first + 1 {
+        return
+    }
+    let (index, max) = farthest(line, first, last, deviation);
+    if max > tolerance {
+        kept.insert(index);
+        douglas_peucker(line, first, index, tolerance, deviation, kept);
+        douglas_peucker(line, index, last, tolerance, deviation, kept);
+    }
+}
+
+/// Point between `first` and `last` the farthest from their chord, with its deviation.
+fn farthest(line: &[LatLon], first: usize, last: usize, deviation: &Deviation) -> (usize, f64) {
+    (first + 1..last)
+        .map(|index| (index, deviation(&line[index], &line[first], &line[last])))
+        .fold((first, f64::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a })
+}
+
+fn chords(kept: &BTreeSet<usize>) -> Vec<(usize, usize)> {
+    kept.iter().zip(kept.iter().skip(1)).map(|(first, last)| (*first, *last)).collect()
+}
+
+/// A chord that crosses another one and replaces points, so that it can be split, with its line.
+fn crossing(lines: &[Vec<LatLon>], kept: &[BTreeSet<usize>]) -> Option<(usize, (usize, usize))> {
+    let chords: Vec<(usize, (usize, usize))> = kept.iter()
+        .enumerate()
+        .flat_map(|(line, kept)| chords(kept).into_iter().map(move |chord| (line, chord)))
+        .collect();
+    let ends = |(line, (first, last)): (usize, (usize, usize))| (&lines[line][first], &lines[line][last]);
+    let same = |a: &LatLon, b: &LatLon| a.lat == b.lat && a.lon == b.lon;
+
+    for (index, &a) in chords.iter().enumerate() {
+        for &b in &chords[index + 1..] {
+            let ((a1, a2), (b1, b2)) = (ends(a), ends(b));
+            // consecutive chords share an end
+            if same(a1, b1) || same(a1, b2) || same(a2, b1) || same(a2, b2) {
+                continue;
+            }
+            if geo::segments_cross(a1, a2, b1, b2) {
+                // chords that replace no point cross as the original lines do
+                if let Some(chord) = [a, b].into_iter().find(|(_, (first, last))| last - first > 1) {
+                    return Some(chord)
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Height of a point above the chord of a limit, `side` being 1 for the north one and -1 for the
+/// south one, infinite when the chord would be more permissive than the point.
+fn vertical(v: &LatLon, a: &LatLon, b: &LatLon, side: f64) -> f64 {
+    if a.lon == b.lon {
+        return f64::INFINITY
+    }
+    let lat = a.lat + (v.lon - a.lon) / (b.lon - a.lon) * (b.lat - a.lat);
+    let height = side * (v.lat - lat);
+    if height < 0.0 {
+        f64::INFINITY
+    } else {
+        geo::EARTH_RADIUS * height.to_radians()
+    }
+}
+
+fn select(points: &[LatLon], kept: &BTreeSet<usize>) -> Vec<LatLon> {
+    kept.iter().map(|index| points[*index].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::race::{LatLon, Limits, MultiPolygon, Polygon, Race, Waypoint, WaypointKind};
+
+    use super::simplify;
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    /// Race with a single buoy, and neither zones to avoid nor ice limits.
+    fn race() -> Race {
+        Race {
+            id: None,
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: latlon(46.5, -1.8),
+            waypoints: vec![Waypoint {
+                name: String::from("1"),
+                kind: WaypointKind::Buoy,
+                radius: Some(5),
+                latlons: vec![latlon(43.0, -9.5)],
+                side: None,
+                group: None,
+                engine: None,
+                checkpoint: None,
+                to_avoid: None,
+            }],
+            ice_limits: None,
+        }
+    }
+
+    /// Jagged line around the earth from west to east, `points` long.
+    fn limit(lat: f64, points: usize) -> Vec<LatLon> {
+        (0..points)
+            .map(|index| {
+                let lon = -180.0 + 360.0 * index as f64 / (points - 1) as f64;
+                latlon(lat + 3.0 * (index as f64 * 0.7).sin() + (index as f64 * 2.3).cos(), lon)
+            })
+            .collect()
+    }
+
+    /// Latitude of the line at the longitude, its longitudes being sorted.
+    fn lat_at(line: &[LatLon], lon: f64) -> f64 {
+        let index = line.windows(2).position(|s| s[0].lon <= lon && lon <= s[1].lon).unwrap();
+        let (a, b) = (&line[index], &line[index + 1]);
+        a.lat + (lon - a.lon) / (b.lon - a.lon) * (b.lat - a.lat)
+    }
+
+    #[test]
+    fn never_makes_the_ice_limits_more_permissive() {
+        let mut race = race();
+        let limits = Limits { north: limit(-45.0, 500), south: limit(-60.0, 500), max_lat: 80.0, min_lat: -70.0 };
+        race.ice_limits = Some(limits.clone());
+
+        simplify(&mut race, 60.0);
+
+        let simplified = race.ice_limits.unwrap();
+        assert!(simplified.north.len() < limits.north.len());
+        assert!(simplified.south.len() < limits.south.len());
+        for point in &limits.north {
+            assert!(lat_at(&simplified.north, point.lon) <= point.lat + 1e-9);
+        }
+        for point in &limits.south {
+            assert!(lat_at(&simplified.south, point.lon) >= point.lat - 1e-9);
+        }
+        // the ends of the limits are kept, around the whole earth
+        assert_eq!(simplified.north[0].lon, -180.0);
+        assert_eq!(simplified.north[simplified.north.len() - 1].lon, 180.0);
+    }
+
+    #[test]
+    fn keeps_the_rings_of_zones_closed() {
+        let mut race = race();
+        let mut exterior: Vec<LatLon> = (0..100)
+            .map(|index| {
+                let angle = (index as f64 / 100.0 * 360.0).to_radians();
+                let radius = 1.0 + 0.01 * (index % 2) as f64;
+                latlon(44.0 + radius * angle.sin(), -8.0 + radius * angle.cos())
+            })
+            .collect();
+        exterior.push(exterior[0].clone());
+        race.waypoints[0].to_avoid = Some(MultiPolygon(vec![Polygon { exterior: exterior.clone(), holes: Vec::new() }]));
+
+        simplify(&mut race, 5.0);
+
+        let simplified = &race.waypoints[0].to_avoid.as_ref().unwrap().0[0].exterior;
+        assert!(simplified.len() >= 4 && simplified.len() < exterior.len() / 2);
+        let (first, last) = (&simplified[0], &simplified[simplified.len() - 1]);
+        assert!(first.lat == last.lat && first.lon == last.lon);
+    }
+}
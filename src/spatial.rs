@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use rstar::primitives::{GeomWithData, Line};
+use rstar::{RTree, AABB};
+
+use crate::course;
+use crate::geo;
+use crate::race::{LatLon, Race};
+
+/// Distance between the points of the course polylines indexed, in nautical miles.
+const COURSE_STEP: f64 = 20.0;
+
+/// Area of the earth, longitudes going east from `west` to `east`, across the antimeridian when
+/// `east` is less than `west`.
+#[derive(Debug, Clone)]
+pub(crate) struct BoundingBox {
+    pub(crate) west: f64,
+    pub(crate) south: f64,
+    pub(crate) east: f64,
+    pub(crate) north: f64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Area {
+    Box(BoundingBox),
+    /// points within `radius` nautical miles of `center`
+    Circle { center: LatLon, radius: f64 },
+}
+
+/// Segment in the plane of longitudes and latitudes, a point being a segment from itself to itself,
+/// with the index of its race.
+type Entry = GeomWithData<Line<[f64; 2]>, usize>;
+
+/// R-tree of the geometry of races: their start, their waypoints and their course polyline, split
+/// along the antimeridian so that every segment is within [-180, 180].
+pub(crate) struct SpatialIndex {
+    tree: RTree<Entry>,
+}
+
+impl SpatialIndex {
+
+    pub(crate) fn new(races: &[Race]) -> Self {
+        let mut entries = Vec::new();
+        for (index, race) in races.iter().enumerate() {
+            let point = |p: &LatLon| {
+                let p = LatLon { lat: p.lat, lon: geo::normalize_lon(p.lon) };
+                Entry::new(Line::new(position(&p), position(&p)), index)
+            };
+            let lines = |points: &[LatLon]| geo::split_line(&geo::unwrap(points)).into_iter()
+                .flat_map(|part| part.windows(2)
+                    .map(|segment| Entry::new(Line::new(position(&segment[0]), position(&segment[1])), index))
+                    .collect::<Vec<_>>());
+
+            entries.push(point(&race.start));
+            for waypoint in &race.waypoints {
+                match waypoint.latlons.as_slice() {
+                    [center] => entries.push(point(center)),
+                    gate => entries.extend(lines(gate)),
+                }
+            }
+            entries.extend(lines(&course::course(race, COURSE_STEP).polyline));
+        }
+        SpatialIndex { tree: RTree::bulk_load(entries) }
+    }
+
+    /// Indices of the races having a point or a segment in the area.
+    pub(crate) fn search(&self, area: &Area) -> HashSet<usize> {
+        let bounds = match area {
+            Area::Box(bounds) => bounds.clone(),
+            Area::Circle { center, radius } => circle_bounds(center, *radius),
+        };
+
+        let mut res = HashSet::new();
+        for envelope in envelopes(&bounds) {
+            for entry in self.tree.locate_in_envelope_intersecting(&envelope) {
+                if res.contains(&entry.data) {
+                    continue;
+                }
+                let (from, to) = (latlon(entry.geom().from), latlon(entry.geom().to));
+                let inside = match area {
+                    Area::Box(_) => in_envelope(&envelope, &from, &to),
+                    Area::Circle { center, radius } => geo::distance_to_line(center, &[from, to]).is_some_and(|d| d <= *radius),
+                };
+                if inside {
+                    res.insert(entry.data);
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Envelopes of a bounding box within [-180, 180], two when it goes across the antimeridian.
+fn envelopes(bounds: &BoundingBox) -> Vec<AABB<[f64; 2]>> {
+    let envelope = |west: f64, east: f64| AABB::from_corners([west, bounds.south], [east, bounds.north]);
+    if bounds.east - bounds.west >= 360.0 {
+        return vec![envelope(-180.0, 180.0)]
+    }
+    let width = (bounds.east - bounds.west).rem_euclid(360.0);
+    let west = geo::normalize_lon(bounds.west);
+    let east = west + width;
+    if east <= 180.0 {
+        vec![envelope(west, east)]
+    } else {
+        vec![envelope(west, 180.0), envelope(-180.0, east - 360.0)]
+    }
+}
+
+/// Bounding box of a circle, going round the world when it reaches a pole.
+fn circle_bounds(center: &LatLon, radius: f64) -> BoundingBox {
+    let angle = (radius / geo::EARTH_RADIUS).min(std::f64::consts::PI);
+    let (south, north) = ((center.lat - angle.to_degrees()).max(-90.0), (center.lat + angle.to_degrees()).min(90.0));
+    let ratio = angle.sin() / center.lat.to_radians().cos();
+    if south <= -90.0 || north >= 90.0 || angle >= std::f64::consts::FRAC_PI_2 || ratio >= 1.0 {
+        return BoundingBox { west: -180.0, south, east: 180.0, north }
+    }
+    let half_width = ratio.asin().to_degrees();
+    BoundingBox { west: center.lon - half_width, south, east: center.lon + half_width, north }
+}
+
+/// Whether a segment, within [-180, 180], has a point in the envelope: one of its ends is in it, or
+/// it crosses one of its sides.
+fn in_envelope(envelope: &AABB<[f64; 2]>, from: &LatLon, to: &LatLon) -> bool {
+    let ([west, south], [east, north]) = (envelope.lower(), envelope.upper());
+    let contains = |p: &LatLon| p.lon >= west && p.lon <= east && p.lat >= south && p.lat <= north;
+    let corners = [
+        LatLon { lat: south, lon: west },
+        LatLon { lat: south, lon: east },
+        LatLon { lat: north, lon: east },
+        LatLon { lat: north, lon: west },
+    ];
+    contains(from) || contains(to)
+        || (0..4).any(|i| geo::segments_cross(from, to, &corners[i], &corners[(i + 1) % 4]))
+}
+
+/// Points of the index are [lon, lat].
+fn position(point: &LatLon) -> [f64; 2] {
+    [point.lon, point.lat]
+}
+
+fn latlon(position: [f64; 2]) -> LatLon {
+    LatLon { lat: position[1], lon: position[0] }
+}
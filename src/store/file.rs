@@ -0,0 +1,650 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use rocket::futures::stream::{self, StreamExt};
+use rocket::tokio::task;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::history::Revision;
+use crate::race::{Race, RaceError, RaceId};
+use crate::schema;
+use crate::schema::Versioned;
+use crate::store::format::Format;
+use crate::store::RaceStore;
+use crate::trash::TrashEntry;
+
+/// Number of files read in parallel when loading a history or the trash.
+const MAX_CONCURRENT_READS: usize = 16;
+
+/// Stores each race as a file named after its id, in `races_dir` or `archived_dir`. Race files
+/// can be YAML, JSON or TOML, new ones are written in `format`.
+///
+/// The history of each race is kept in `races_dir/.history/<race id>/<revision number>.<ext>`, and
+/// deleted races in `races_dir/.trash/<race id>.<ext>`, also written in `format`.
+///
+/// Races are kept in an in-memory index, built at startup and refreshed both by our own writes
+/// and by watching the directories, so that files edited by hand are picked up without a restart.
+pub(crate) struct FileStore {
+    files: Arc<Files>,
+    /// number of files the watcher reloaded
+    reloads: Arc<AtomicU64>,
+    _watcher: RecommendedWatcher,
+}
+
+/// Directories of the store and the blocking operations on them, run on the blocking thread pool.
+struct Files {
+    races_dir: PathBuf,
+    archived_dir: PathBuf,
+    history_dir: PathBuf,
+    trash_dir: PathBuf,
+    format: Format,
+    index: Arc<RwLock<Index>>,
+}
+
+#[derive(Default)]
+struct Index {
+    races: HashMap<RaceId, Race>,
+    archived: HashMap<RaceId, Race>,
+    /// races stored in several files, with the names of these files, reported as conflicts until
+    /// all but one are removed
+    duplicates: HashMap<RaceId, String>,
+}
+
+impl Index {
+    fn races_mut(&mut self, archived: bool) -> &mut HashMap<RaceId, Race> {
+        if archived {
+            &mut self.archived
+        } else {
+            &mut self.races
+        }
+    }
+}
+
+impl FileStore {
+
+    pub(crate) fn new<P: Into<PathBuf>, Q: Into<PathBuf>>(races_dir: P, archived_dir: Q, format: Format) -> Result<Self> {
+        let races_dir = Files::create_dir(&races_dir.into());
+        let archived_dir = Files::create_dir(&archived_dir.into());
+        let history_dir = Files::create_dir(&races_dir.join(".history"));
+        let trash_dir = Files::create_dir(&races_dir.join(".trash"));
+
+        let mut index = Index::default();
+        Files::load_dir(&mut index, &races_dir, false);
+        Files::load_dir(&mut index, &archived_dir, true);
+        info!("Loaded {} races and {} archived races", index.races.len(), index.archived.len());
+        let index = Arc::new(RwLock::new(index));
+        let reloads = Arc::new(AtomicU64::new(0));
+
+        let mut watcher = {
+            let index = index.clone();
+            let reloads = reloads.clone();
+            let races_dir = races_dir.clone();
+            let archived_dir = archived_dir.clone();
+            notify::recommended_watcher(move |event: notify::Result<Event>| {
+                match event {
+                    // files are read once fully written, moved or removed, not on every write
+                    Ok(event) if matches!(event.kind,
+                        EventKind::Access(AccessKind::Close(AccessMode::Write))
+                        | EventKind::Modify(ModifyKind::Name(_))
+                        | EventKind::Remove(_)) => {
+                        for path in event.paths {
+                            let archived = match path.parent() {
+                                Some(parent) if parent == races_dir => false,
+                                Some(parent) if parent == archived_dir => true,
+                                _ => continue,
+                            };
+                            Files::reload(&index, &path, archived);
+                            reloads.fetch_add(1, Ordering::SeqCst);
+                        }
+                    },
+                    Ok(_) => {},
+                    Err(e) => error!("Error watching races : {}", e),
+                }
+            })?
+        };
+        watcher.watch(&races_dir, RecursiveMode::NonRecursive)?;
+        watcher.watch(&archived_dir, RecursiveMode::NonRecursive)?;
+
+        let files = Files { races_dir, archived_dir, history_dir, trash_dir, format, index };
+        Ok(FileStore { files: Arc::new(files), reloads, _watcher: watcher })
+    }
+
+    /// Runs a file operation on the blocking thread pool, so that a slow disk doesn't hold up the
+    /// async workers.
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+        where T: Send + 'static, F: FnOnce(&Files) -> Result<T> + Send + 'static {
+        let files = self.files.clone();
+        task::spawn_blocking(move || f(&files)).await?
+    }
+
+    /// Reads the documents, in order, with at most `MAX_CONCURRENT_READS` files open at a time.
+    async fn read_all<T: DeserializeOwned + Send + 'static>(paths: Vec<PathBuf>) -> Vec<(PathBuf, Result<T>)> {
+        stream::iter(paths)
+            .map(|path| async move {
+                let res = {
+                    let path = path.clone();
+                    task::spawn_blocking(move || Files::read::<T>(&path)).await
+                };
+                (path, res.unwrap_or_else(|e| Err(e.into())))
+            })
+            .buffered(MAX_CONCURRENT_READS)
+            .collect()
+            .await
+    }
+}
+
+impl Files {
+
+    fn create_dir(dir: &PathBuf) -> PathBuf {
+        if !dir.exists() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                panic!("Error creating dir {:?} : {}", dir, e);
+            }
+        } else if !dir.is_dir() {
+            panic!("{:?} is not a directory", dir);
+        }
+        dir.canonicalize().unwrap_or_else(|e| panic!("Error resolving dir {:?} : {}", dir, e))
+    }
+
+    fn race_id(path: &Path) -> Option<RaceId> {
+        Format::from_path(path)?;
+        let id = path.file_prefix()?.to_string_lossy();
+        match RaceId::parse(&id) {
+            Ok(race_id) => Some(race_id),
+            Err(e) => {
+                warn!("Ignoring file {:?} : {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn read<T: DeserializeOwned>(path: &Path) -> Result<T> {
+        let reader = BufReader::new(File::open(path)?);
+        Format::from_path(path).unwrap_or_default().read(reader)
+    }
+
+    /// File of the race in `dir`, whatever its format. A race stored in several formats is an
+    /// error, as we can't tell which file is the right one.
+    fn find(dir: &Path, race_id: &RaceId) -> Result<Option<PathBuf>> {
+        let mut paths: Vec<PathBuf> = Format::EXTENSIONS.iter()
+            .map(|ext| dir.join(format!("{}.{}", race_id, ext)))
+            .filter(|path| path.is_file())
+            .collect();
+
+        if paths.len() > 1 {
+            let files = paths.iter()
+                .filter_map(|path| path.file_name())
+                .map(|name| name.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(RaceError::DuplicateFiles(race_id.to_string(), files).into())
+        }
+        Ok(paths.pop())
+    }
+
+    fn read_race(path: &Path, race_id: RaceId, archived: bool) -> Result<Race> {
+        let mut race = schema::upgrade(Self::read(path)?)?;
+        race.id = Some(race_id);
+        race.archived = archived;
+        Ok(race)
+    }
+
+    fn load_dir(index: &mut Index, dir: &Path, archived: bool) {
+        let paths = match fs::read_dir(dir) {
+            Ok(paths) => paths,
+            Err(e) => {
+                error!("Error reading dir {:?} : {}", dir, e);
+                return;
+            }
+        };
+
+        let mut race_ids = HashSet::new();
+        for entry in paths.flatten() {
+            match entry.metadata() {
+                Ok(metadata) if metadata.is_file() => {
+                    if let Some(race_id) = Self::race_id(&entry.path()) {
+                        race_ids.insert(race_id);
+                    }
+                },
+                Ok(_) => {},
+                Err(_) => error!("Couldn't get metadata for {:?}", entry.path()),
+            }
+        }
+
+        for race_id in race_ids {
+            match Self::find(dir, &race_id) {
+                Ok(Some(path)) => match Self::read_race(&path, race_id.clone(), archived) {
+                    Ok(race) => { index.races_mut(archived).insert(race_id, race); },
+                    Err(e) => error!("Error reading file {:?} : {:?}", path, e),
+                },
+                Ok(None) => {},
+                Err(e) => Self::duplicate(index, race_id, e),
+            }
+        }
+    }
+
+    /// Records a race stored in several files, so that it is reported as a conflict rather than
+    /// missing.
+    fn duplicate(index: &mut Index, race_id: RaceId, error: anyhow::Error) {
+        error!("Ignoring race : {}", error);
+        if let Some(RaceError::DuplicateFiles(_, files)) = error.downcast_ref::<RaceError>() {
+            index.duplicates.insert(race_id, files.clone());
+        }
+    }
+
+    /// Refreshes the index entry of a race file after a change on disk.
+    fn reload(index: &RwLock<Index>, path: &Path, archived: bool) {
+        let race_id = match Self::race_id(path) {
+            Some(race_id) => race_id,
+            None => return,
+        };
+        let dir = match path.parent() {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        match Self::find(dir, &race_id) {
+            Ok(Some(path)) => match Self::read_race(&path, race_id.clone(), archived) {
+                Ok(race) => {
+                    debug!("Reloaded race {} from {:?}", race_id, path);
+                    let mut index = index.write().unwrap();
+                    index.duplicates.remove(&race_id);
+                    index.races_mut(archived).insert(race_id, race);
+                },
+                // keep the previous version, the file may still be being written
+                Err(e) => error!("Error reading file {:?} : {:?}", path, e),
+            },
+            Ok(None) => {
+                let mut index = index.write().unwrap();
+                index.duplicates.remove(&race_id);
+                if index.races_mut(archived).remove(&race_id).is_some() {
+                    debug!("Removed race {} from index", race_id);
+                }
+            },
+            Err(e) => {
+                let mut index = index.write().unwrap();
+                index.races_mut(archived).remove(&race_id);
+                Self::duplicate(&mut index, race_id, e);
+            },
+        }
+    }
+
+    fn dir(&self, archived: bool) -> &Path {
+        if archived { &self.archived_dir } else { &self.races_dir }
+    }
+
+    /// Current file of the race, if any.
+    fn file(&self, race_id: &RaceId, archived: bool) -> Result<Option<PathBuf>> {
+        Self::find(self.dir(archived), race_id)
+    }
+
+    /// Path of a new race file, in the configured format.
+    fn new_path(&self, race_id: &RaceId, archived: bool) -> PathBuf {
+        self.dir(archived).join(format!("{}.{}", race_id, self.format.extension()))
+    }
+
+    fn rename(from: &Path, to: &Path) -> Result<()> {
+        match fs::rename(from, to) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Error moving file {:?} to {:?} : {}", from, to, e);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Trash file of the race, if any, whatever its format.
+    fn trash_file(&self, race_id: &RaceId) -> Result<Option<PathBuf>> {
+        Self::find(&self.trash_dir, race_id)
+    }
+
+    /// Fails when the id is taken, by an active or archived race, or by one in the trash.
+    fn available(&self, race_id: &RaceId) -> Result<()> {
+        if self.file(race_id, false)?.is_some() || self.file(race_id, true)?.is_some() {
+            Err(RaceError::AlreadyExists(race_id.to_string()).into())
+        } else if self.trash_file(race_id)?.is_some() {
+            Err(RaceError::InTrash(race_id.to_string()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes the document to a temporary file first, then moves it over `path`, so that a crash
+    /// never leaves a half-written file behind.
+    fn save<T: Serialize>(path: &Path, document: &T) -> Result<()> {
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+
+        let f = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        Format::from_path(path).unwrap_or_default().write(&f, document)?;
+        f.sync_all()?;
+
+        Self::rename(&tmp, path)
+    }
+
+    fn indexed(&self, race_id: &RaceId, race: &Race, archived: bool) -> Race {
+        let mut race = race.clone();
+        race.id = Some(race_id.clone());
+        race.archived = archived;
+        race
+    }
+
+    fn create(&self, race_id: &RaceId, race: &Race) -> Result<()> {
+        let path = self.new_path(race_id, false);
+        self.available(race_id)?;
+        match Self::save(&path, &Versioned::new(race)) {
+            Ok(()) => {
+                self.index.write().unwrap().races.insert(race_id.clone(), self.indexed(race_id, race, false));
+                Ok(())
+            },
+            Err(e) => {
+                error!("Error saving race {:?} : {}", path, e);
+                Err(e)
+            }
+        }
+    }
+
+    fn update(&self, race_id: &RaceId, new_id: &RaceId, race: &Race) -> Result<()> {
+        let path = match self.file(race_id, false)? {
+            Some(path) => path,
+            None => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        // the race keeps the format of its file
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        let new_path = path.with_file_name(format!("{}.{}", new_id, extension));
+        if new_id != race_id {
+            self.available(new_id)?;
+        }
+
+        if let Err(e) = Self::save(&new_path, &Versioned::new(race)) {
+            error!("Error saving race {:?} : {}", new_path, e);
+            return Err(e);
+        }
+        self.index.write().unwrap().races.insert(new_id.clone(), self.indexed(new_id, race, false));
+
+        if new_id != race_id {
+            // the id change. the new file is written, the old one must be removed.
+            if let Err(e) = fs::remove_file(&path) {
+                error!("Error removing file {:?} : {}", path, e);
+                return Err(e.into());
+            }
+            self.index.write().unwrap().races.remove(race_id);
+
+            let history = self.history_dir.join(race_id.as_str());
+            if history.exists() {
+                Self::rename(&history, &self.history_dir.join(new_id.as_str()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, race_id: &RaceId) -> Result<()> {
+        let (path, archived) = match (self.file(race_id, false)?, self.file(race_id, true)?) {
+            (Some(path), _) => (path, false),
+            (None, Some(path)) => (path, true),
+            (None, None) => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        let entry = TrashEntry {
+            deleted_at: Utc::now(),
+            archived,
+            race: Self::read_race(&path, race_id.clone(), archived)?,
+        };
+        Self::save(&self.trash_dir.join(format!("{}.{}", race_id, self.format.extension())), &entry)?;
+
+        match fs::remove_file(&path) {
+            Ok(_) => {
+                self.index.write().unwrap().races_mut(archived).remove(race_id);
+                Ok(())
+            },
+            Err(e) => {
+                error!("Error removing file {:?} : {}", path, e);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn archive(&self, race_id: &RaceId) -> Result<()> {
+        let path = match self.file(race_id, false)? {
+            Some(path) => path,
+            None => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        if self.file(race_id, true)?.is_some() {
+            Err(RaceError::AlreadyExists(race_id.to_string()).into())
+        } else {
+            Self::rename(&path, &self.archived_dir.join(path.file_name().unwrap_or_default()))?;
+            let mut index = self.index.write().unwrap();
+            if let Some(mut race) = index.races.remove(race_id) {
+                race.archived = true;
+                index.archived.insert(race_id.clone(), race);
+            }
+            Ok(())
+        }
+    }
+
+    fn restore(&self, race_id: &RaceId) -> Result<()> {
+        let archived = match self.file(race_id, true)? {
+            Some(archived) => archived,
+            None => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        if self.file(race_id, false)?.is_some() {
+            Err(RaceError::AlreadyExists(race_id.to_string()).into())
+        } else {
+            Self::rename(&archived, &self.races_dir.join(archived.file_name().unwrap_or_default()))?;
+            let mut index = self.index.write().unwrap();
+            if let Some(mut race) = index.archived.remove(race_id) {
+                race.archived = false;
+                index.races.insert(race_id.clone(), race);
+            }
+            Ok(())
+        }
+    }
+
+    fn add_revision(&self, race_id: &RaceId, revision: &Revision) -> Result<()> {
+        let dir = self.history_dir.join(race_id.as_str());
+        fs::create_dir_all(&dir)?;
+
+        let exists = Format::EXTENSIONS.iter().any(|ext| dir.join(format!("{}.{}", revision.number, ext)).exists());
+        if exists {
+            return Err(anyhow::anyhow!("Revision {} of race {} already exists", revision.number, race_id))
+        }
+        Self::save(&dir.join(format!("{}.{}", revision.number, self.format.extension())), revision)
+    }
+
+    fn migrate(&self) -> Result<usize> {
+        let mut count = 0;
+        for archived in [false, true] {
+            for entry in fs::read_dir(self.dir(archived))?.flatten() {
+                let path = entry.path();
+                if !path.is_file() || Self::race_id(&path).is_none() {
+                    continue;
+                }
+
+                let document: Value = Self::read(&path)?;
+                if schema::version(&document) < schema::VERSION {
+                    let race = schema::upgrade(document).with_context(|| format!("Error migrating {:?}", path))?;
+                    Self::save(&path, &Versioned::new(&race))?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn revision_paths(&self, race_id: &RaceId) -> Result<Vec<PathBuf>> {
+        let dir = self.history_dir.join(race_id.as_str());
+        if !dir.exists() {
+            return Ok(Vec::new())
+        }
+
+        Ok(fs::read_dir(&dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| Format::from_path(path).is_some())
+            .collect())
+    }
+
+    fn trash_paths(&self) -> Result<Vec<(RaceId, PathBuf)>> {
+        Ok(fs::read_dir(&self.trash_dir)?
+            .flatten()
+            .filter_map(|entry| Self::race_id(&entry.path()).map(|race_id| (race_id, entry.path())))
+            .collect())
+    }
+
+    fn untrash(&self, race_id: &RaceId) -> Result<()> {
+        let trash_path = match self.trash_file(race_id)? {
+            Some(path) => path,
+            None => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+        if self.file(race_id, false)?.is_some() || self.file(race_id, true)?.is_some() {
+            return Err(RaceError::AlreadyExists(race_id.to_string()).into())
+        }
+
+        let entry: TrashEntry = Self::read(&trash_path)?;
+        Self::save(&self.new_path(race_id, entry.archived), &Versioned::new(&entry.race))?;
+        self.index.write().unwrap().races_mut(entry.archived).insert(race_id.clone(), self.indexed(race_id, &entry.race, entry.archived));
+
+        if let Err(e) = fs::remove_file(&trash_path) {
+            error!("Error removing file {:?} : {}", trash_path, e);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn purge(&self, race_id: &RaceId) -> Result<()> {
+        let trash_path = match self.trash_file(race_id)? {
+            Some(path) => path,
+            None => return Err(RaceError::NotFound(race_id.to_string()).into()),
+        };
+
+        fs::remove_file(&trash_path)?;
+        let history = self.history_dir.join(race_id.as_str());
+        if history.exists() {
+            fs::remove_dir_all(&history)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RaceStore for FileStore {
+
+    async fn list(&self, archived: bool) -> Result<Vec<Race>> {
+        let index = self.files.index.read().unwrap();
+        let races = if archived { &index.archived } else { &index.races };
+        Ok(races.values().cloned().collect())
+    }
+
+    async fn get(&self, race_id: &RaceId) -> Result<Option<Race>> {
+        let index = self.files.index.read().unwrap();
+        if let Some(files) = index.duplicates.get(race_id) {
+            return Err(RaceError::DuplicateFiles(race_id.to_string(), files.clone()).into())
+        }
+        Ok(index.races.get(race_id).or_else(|| index.archived.get(race_id)).cloned())
+    }
+
+    async fn create(&self, race_id: &RaceId, race: &Race) -> Result<()> {
+        let (race_id, race) = (race_id.clone(), race.clone());
+        self.blocking(move |files| files.create(&race_id, &race)).await
+    }
+
+    async fn update(&self, race_id: &RaceId, new_id: &RaceId, race: &Race) -> Result<()> {
+        let (race_id, new_id, race) = (race_id.clone(), new_id.clone(), race.clone());
+        self.blocking(move |files| files.update(&race_id, &new_id, &race)).await
+    }
+
+    async fn delete(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |files| files.delete(&race_id)).await
+    }
+
+    async fn archive(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |files| files.archive(&race_id)).await
+    }
+
+    async fn restore(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |files| files.restore(&race_id)).await
+    }
+
+    async fn add_revision(&self, race_id: &RaceId, revision: &Revision) -> Result<()> {
+        let (race_id, revision) = (race_id.clone(), revision.clone());
+        self.blocking(move |files| files.add_revision(&race_id, &revision)).await
+    }
+
+    async fn revisions(&self, race_id: &RaceId) -> Result<Vec<Revision>> {
+        let paths = {
+            let race_id = race_id.clone();
+            self.blocking(move |files| files.revision_paths(&race_id)).await?
+        };
+
+        let mut res = Vec::new();
+        for (_, revision) in Self::read_all::<Revision>(paths).await {
+            let mut revision = revision?;
+            revision.race.id = Some(race_id.clone());
+            res.push(revision);
+        }
+        res.sort_by_key(|r| r.number);
+        Ok(res)
+    }
+
+    async fn trash(&self) -> Result<Vec<TrashEntry>> {
+        let (race_ids, paths): (Vec<RaceId>, Vec<PathBuf>) = self.blocking(|files| files.trash_paths()).await?
+            .into_iter()
+            .unzip();
+
+        let mut res = Vec::new();
+        for (race_id, (path, trashed)) in race_ids.into_iter().zip(Self::read_all::<TrashEntry>(paths).await) {
+            match trashed {
+                Ok(mut trashed) => {
+                    trashed.race.id = Some(race_id);
+                    trashed.race.archived = trashed.archived;
+                    res.push(trashed);
+                },
+                Err(e) => error!("Error reading file {:?} : {:?}", path, e),
+            }
+        }
+        Ok(res)
+    }
+
+    async fn untrash(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |files| files.untrash(&race_id)).await
+    }
+
+    async fn purge(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |files| files.purge(&race_id)).await
+    }
+
+    async fn migrate(&self) -> Result<usize> {
+        self.blocking(|files| files.migrate()).await
+    }
+
+    fn reloads(&self) -> u64 {
+        self.reloads.load(Ordering::SeqCst)
+    }
+}
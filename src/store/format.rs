@@ -0,0 +1,60 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Serialization format of a race file, given by its extension.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl Format {
+    /// Extensions of the files that are read, whatever the format used for writing.
+    pub(crate) const EXTENSIONS: [&'static str; 4] = ["yaml", "yml", "json", "toml"];
+
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "yaml" | "yml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Yaml => "yaml",
+            Format::Json => "json",
+            Format::Toml => "toml",
+        }
+    }
+
+    pub(crate) fn read<T: DeserializeOwned, R: Read>(self, mut reader: R) -> Result<T> {
+        match self {
+            Format::Yaml => Ok(serde_yaml::from_reader(reader)?),
+            Format::Json => Ok(serde_json::from_reader(reader)?),
+            Format::Toml => {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Ok(toml::from_str(&content)?)
+            },
+        }
+    }
+
+    pub(crate) fn write<T: Serialize, W: Write>(self, mut writer: W, document: &T) -> Result<()> {
+        match self {
+            Format::Yaml => serde_yaml::to_writer(writer, document)?,
+            Format::Json => serde_json::to_writer_pretty(writer, document)?,
+            // going through a value puts the tables after the plain values, as TOML requires
+            Format::Toml => writer.write_all(toml::to_string(&toml::Value::try_from(document)?)?.as_bytes())?,
+        }
+        Ok(())
+    }
+}
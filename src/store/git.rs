@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use git2::{DiffOptions, IndexAddOption, Repository, Signature, Sort};
+use log::info;
+use rocket::tokio::task;
+
+use crate::history::{Commit, Revision};
+use crate::race::{Race, RaceId};
+use crate::schema;
+use crate::store::file::FileStore;
+use crate::store::format::Format;
+use crate::store::RaceStore;
+use crate::trash::TrashEntry;
+
+/// Files that are not versioned: the history and trash of the file store, and its temporary files.
+const GIT_IGNORE: [&str; 3] = [".history/", ".trash/", ".*.tmp"];
+
+/// Stores races like `FileStore`, in a `races_dir` that is a git repository, committing every change.
+pub(crate) struct GitStore {
+    files: FileStore,
+    repo: Arc<Mutex<Repository>>,
+    /// path of `archived_dir`, relative to `races_dir`
+    archived_path: PathBuf,
+    author: String,
+    email: String,
+}
+
+impl GitStore {
+
+    pub(crate) fn new<P: AsRef<Path>, Q: AsRef<Path>>(races_dir: P, archived_dir: Q, format: Format, author: String, email: String) -> Result<Self> {
+        let files = FileStore::new(races_dir.as_ref(), archived_dir.as_ref(), format)?;
+
+        let races_dir = races_dir.as_ref().canonicalize()?;
+        let archived_path = archived_dir.as_ref().canonicalize()?
+            .strip_prefix(&races_dir)
+            .map_err(|_| anyhow!("archived dir {:?} must be inside races dir {:?} to be versioned", archived_dir.as_ref(), races_dir))?
+            .to_path_buf();
+
+        let repo = match Repository::open(&races_dir) {
+            Ok(repo) => repo,
+            Err(_) => {
+                info!("Initializing git repository in {:?}", races_dir);
+                Repository::init(&races_dir)?
+            }
+        };
+
+        Self::ignore(&races_dir.join(".gitignore"))?;
+
+        let mut paths = Self::race_paths(&archived_path, &["*"]);
+        paths.push(String::from(".gitignore"));
+        Self::commit_paths(&repo, &paths, &author, &email, "Import races")?;
+        Ok(GitStore { files, repo: Arc::new(Mutex::new(repo)), archived_path, author, email })
+    }
+
+    /// Adds the entries of `GIT_IGNORE` missing from the `.gitignore` file, keeping the others.
+    fn ignore(git_ignore: &Path) -> Result<()> {
+        let mut content = if git_ignore.exists() { fs::read_to_string(git_ignore)? } else { String::new() };
+        let missing: Vec<&str> = GIT_IGNORE.iter()
+            .filter(|entry| !content.lines().any(|line| line.trim() == **entry))
+            .copied()
+            .collect();
+        if missing.is_empty() {
+            return Ok(())
+        }
+
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        for entry in missing {
+            content.push_str(entry);
+            content.push('\n');
+        }
+        fs::write(git_ignore, content)?;
+        Ok(())
+    }
+
+    /// Pathspecs, relative to `races_dir`, of the files of races, active or archived, in any format,
+    /// `names` being race ids or patterns.
+    fn race_paths(archived_path: &Path, names: &[&str]) -> Vec<String> {
+        names.iter()
+            .flat_map(|name| Format::EXTENSIONS.iter().map(move |ext| format!("{}.{}", name, ext)))
+            .flat_map(|file| [archived_path.join(&file).to_string_lossy().into_owned(), file])
+            .collect()
+    }
+
+    /// Runs a git operation on the blocking thread pool, so that a slow disk doesn't hold up the
+    /// async workers.
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+        where T: Send + 'static, F: FnOnce(&Repository) -> Result<T> + Send + 'static {
+        let repo = self.repo.clone();
+        task::spawn_blocking(move || f(&repo.lock().unwrap())).await?
+    }
+
+    /// Commits the changes of the files of the races, leaving any other change of the working
+    /// directory out.
+    async fn commit(&self, race_ids: &[&RaceId], message: String) -> Result<()> {
+        let race_ids: Vec<&str> = race_ids.iter().map(|race_id| race_id.as_str()).collect();
+        let paths = Self::race_paths(&self.archived_path, &race_ids);
+        let (author, email) = (self.author.clone(), self.email.clone());
+        self.blocking(move |repo| Self::commit_paths(repo, &paths, &author, &email, &message)).await
+    }
+
+    /// Commits the changes of the files matching `paths`, if any.
+    fn commit_paths(repo: &Repository, paths: &[String], author: &str, email: &str, message: &str) -> Result<()> {
+        let mut index = repo.index()?;
+        index.add_all(paths, IndexAddOption::DEFAULT, None)?;
+        index.update_all(paths, None)?;
+        index.write()?;
+        let tree_id = index.write_tree()?;
+
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(_) => None,
+        };
+        if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree_id) {
+            return Ok(())
+        }
+
+        let tree = repo.find_tree(tree_id)?;
+        let signature = Signature::now(author, email)?;
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        Ok(())
+    }
+
+    fn log(repo: &Repository, archived_path: &Path, race_id: Option<&RaceId>) -> Result<Vec<Commit>> {
+        let mut revwalk = repo.revwalk()?;
+        if revwalk.push_head().is_err() {
+            // no commit yet
+            return Ok(Vec::new())
+        }
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+        let mut res = Vec::new();
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+
+            if let Some(race_id) = race_id {
+                let mut options = DiffOptions::new();
+                options.pathspec(format!("{}.*", race_id));
+                options.pathspec(archived_path.join(format!("{}.*", race_id)));
+
+                let parent_tree = match commit.parent(0) {
+                    Ok(parent) => Some(parent.tree()?),
+                    Err(_) => None,
+                };
+                let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut options))?;
+                if diff.deltas().len() == 0 {
+                    continue;
+                }
+            }
+
+            let author = commit.author();
+            res.push(Commit {
+                id: commit.id().to_string(),
+                message: commit.message().unwrap_or_default().trim().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                email: author.email().unwrap_or_default().to_string(),
+                timestamp: Utc.timestamp(commit.time().seconds(), 0),
+            });
+        }
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl RaceStore for GitStore {
+
+    async fn list(&self, archived: bool) -> Result<Vec<Race>> {
+        self.files.list(archived).await
+    }
+
+    async fn get(&self, race_id: &RaceId) -> Result<Option<Race>> {
+        self.files.get(race_id).await
+    }
+
+    async fn create(&self, race_id: &RaceId, race: &Race) -> Result<()> {
+        self.files.create(race_id, race).await?;
+        self.commit(&[race_id], format!("Create race {}", race_id)).await
+    }
+
+    async fn update(&self, race_id: &RaceId, new_id: &RaceId, race: &Race) -> Result<()> {
+        self.files.update(race_id, new_id, race).await?;
+        if new_id != race_id {
+            self.commit(&[race_id, new_id], format!("Rename race {} to {}", race_id, new_id)).await
+        } else {
+            self.commit(&[race_id], format!("Update race {}", race_id)).await
+        }
+    }
+
+    async fn delete(&self, race_id: &RaceId) -> Result<()> {
+        self.files.delete(race_id).await?;
+        self.commit(&[race_id], format!("Delete race {}", race_id)).await
+    }
+
+    async fn archive(&self, race_id: &RaceId) -> Result<()> {
+        self.files.archive(race_id).await?;
+        self.commit(&[race_id], format!("Archive race {}", race_id)).await
+    }
+
+    async fn restore(&self, race_id: &RaceId) -> Result<()> {
+        self.files.restore(race_id).await?;
+        self.commit(&[race_id], format!("Restore race {}", race_id)).await
+    }
+
+    async fn add_revision(&self, race_id: &RaceId, revision: &Revision) -> Result<()> {
+        self.files.add_revision(race_id, revision).await
+    }
+
+    async fn revisions(&self, race_id: &RaceId) -> Result<Vec<Revision>> {
+        self.files.revisions(race_id).await
+    }
+
+    async fn trash(&self) -> Result<Vec<TrashEntry>> {
+        self.files.trash().await
+    }
+
+    async fn untrash(&self, race_id: &RaceId) -> Result<()> {
+        self.files.untrash(race_id).await?;
+        self.commit(&[race_id], format!("Restore race {} from trash", race_id)).await
+    }
+
+    async fn purge(&self, race_id: &RaceId) -> Result<()> {
+        self.files.purge(race_id).await
+    }
+
+    async fn migrate(&self) -> Result<usize> {
+        let count = self.files.migrate().await?;
+        let paths = Self::race_paths(&self.archived_path, &["*"]);
+        let (author, email) = (self.author.clone(), self.email.clone());
+        let message = format!("Migrate races to schema version {}", schema::VERSION);
+        self.blocking(move |repo| Self::commit_paths(repo, &paths, &author, &email, &message)).await?;
+        Ok(count)
+    }
+
+    async fn commits(&self, race_id: Option<&RaceId>) -> Result<Option<Vec<Commit>>> {
+        let (archived_path, race_id) = (self.archived_path.clone(), race_id.cloned());
+        let commits = self.blocking(move |repo| Self::log(repo, &archived_path, race_id.as_ref())).await?;
+        Ok(Some(commits))
+    }
+
+    fn reloads(&self) -> u64 {
+        self.files.reloads()
+    }
+}
@@ -0,0 +1,63 @@
+pub(crate) mod file;
+pub(crate) mod format;
+pub(crate) mod git;
+pub(crate) mod sqlite;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::config::StoreConfig;
+use crate::history::{Commit, Revision};
+use crate::race::{Race, RaceId};
+use crate::store::file::FileStore;
+use crate::store::git::GitStore;
+use crate::store::sqlite::SqliteStore;
+use crate::trash::TrashEntry;
+
+/// Storage backend of the races and of their history.
+///
+/// Ids are unique across active, archived and deleted races. Implementations report missing or
+/// conflicting races with `RaceError`, an id taken by a race in the trash with `InTrash`.
+#[async_trait]
+pub(crate) trait RaceStore: Send + Sync {
+    async fn list(&self, archived: bool) -> Result<Vec<Race>>;
+    async fn get(&self, race_id: &RaceId) -> Result<Option<Race>>;
+    async fn create(&self, race_id: &RaceId, race: &Race) -> Result<()>;
+    /// Replaces the active race `race_id`, renaming it and its history to `new_id` when they differ.
+    async fn update(&self, race_id: &RaceId, new_id: &RaceId, race: &Race) -> Result<()>;
+    /// Moves the race to the trash, keeping its history.
+    async fn delete(&self, race_id: &RaceId) -> Result<()>;
+    async fn archive(&self, race_id: &RaceId) -> Result<()>;
+    async fn restore(&self, race_id: &RaceId) -> Result<()>;
+    async fn add_revision(&self, race_id: &RaceId, revision: &Revision) -> Result<()>;
+    /// History of the race, oldest revision first.
+    async fn revisions(&self, race_id: &RaceId) -> Result<Vec<Revision>>;
+    async fn trash(&self) -> Result<Vec<TrashEntry>>;
+    /// Moves a race back from the trash to the active or archived races it was deleted from.
+    async fn untrash(&self, race_id: &RaceId) -> Result<()>;
+    /// Removes a race from the trash along with its history, for good.
+    async fn purge(&self, race_id: &RaceId) -> Result<()>;
+    /// Rewrites the races stored with an older schema to the latest one. Returns their number.
+    async fn migrate(&self) -> Result<usize>;
+    /// Commits touching the race, or the whole catalogue, most recent first.
+    ///
+    /// `None` when the store is not versioned.
+    async fn commits(&self, _race_id: Option<&RaceId>) -> Result<Option<Vec<Commit>>> {
+        Ok(None)
+    }
+    /// Number of times races were reloaded after changing behind the back of the store, such as
+    /// files edited by hand, for what is computed from them to be refreshed.
+    fn reloads(&self) -> u64 {
+        0
+    }
+}
+
+pub(crate) fn init(races_dir: &str, archived_dir: &str, config: &StoreConfig) -> Result<Arc<dyn RaceStore>> {
+    match config {
+        StoreConfig::File { format } => Ok(Arc::new(FileStore::new(races_dir, archived_dir, *format)?)),
+        StoreConfig::Git { author, email, format } => Ok(Arc::new(GitStore::new(races_dir, archived_dir, *format, author.clone(), email.clone())?)),
+        StoreConfig::Sqlite { path } => Ok(Arc::new(SqliteStore::new(path)?)),
+    }
+}
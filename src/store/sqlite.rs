@@ -0,0 +1,302 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use rocket::tokio::task;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+
+use crate::history::Revision;
+use crate::race::{Race, RaceError, RaceId};
+use crate::schema;
+use crate::schema::Versioned;
+use crate::store::RaceStore;
+use crate::trash::TrashEntry;
+
+/// Stores races in an embedded SQLite database, one row per race holding its JSON document, and
+/// one row per revision in their history. Deleted races keep their row, with `deleted_at` set.
+pub(crate) struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS races (
+                id TEXT PRIMARY KEY NOT NULL,
+                archived INTEGER NOT NULL DEFAULT 0,
+                start_time TEXT,
+                data TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS races_archived_start_time ON races (archived, start_time);
+            CREATE TABLE IF NOT EXISTS race_revisions (
+                race_id TEXT NOT NULL,
+                number INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                PRIMARY KEY (race_id, number)
+            );")?;
+
+        let has_deleted_at: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('races') WHERE name = 'deleted_at'",
+            [],
+            |row| row.get(0))?;
+        if !has_deleted_at {
+            conn.execute_batch(
+                "ALTER TABLE races ADD COLUMN deleted_at TEXT;
+                CREATE INDEX IF NOT EXISTS races_deleted_at ON races (deleted_at);")?;
+        }
+
+        Ok(SqliteStore { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Runs the queries on the blocking thread pool, so that a slow disk doesn't hold up the
+    /// async workers.
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+        where T: Send + 'static, F: FnOnce(&mut Connection) -> Result<T> + Send + 'static {
+        let conn = self.conn.clone();
+        task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+
+    fn to_row(race: &Race) -> Result<(Option<String>, String)> {
+        let start_time = race.start_time.map(|t| t.to_rfc3339());
+        let data = serde_json::to_string(&Versioned::new(race))?;
+        Ok((start_time, data))
+    }
+
+    fn from_row(race_id: &str, archived: bool, data: &str) -> Result<Race> {
+        let mut race = schema::upgrade(serde_json::from_str(data)?)?;
+        race.id = Some(RaceId::parse(race_id)?);
+        race.archived = archived;
+        Ok(race)
+    }
+
+    /// Whether the race is archived, `None` when it doesn't exist or is deleted.
+    fn is_archived(conn: &Connection, race_id: &RaceId) -> Result<Option<bool>> {
+        let archived = conn.query_row(
+            "SELECT archived FROM races WHERE id = ?1 AND deleted_at IS NULL",
+            params![race_id.as_str()],
+            |row| row.get(0))
+            .optional()?;
+        Ok(archived)
+    }
+
+    /// Fails when the id is taken, by an active or archived race, or by one in the trash.
+    fn available(conn: &Connection, race_id: &RaceId) -> Result<()> {
+        let deleted: Option<bool> = conn.query_row(
+            "SELECT deleted_at IS NOT NULL FROM races WHERE id = ?1",
+            params![race_id.as_str()],
+            |row| row.get(0))
+            .optional()?;
+        match deleted {
+            None => Ok(()),
+            Some(false) => Err(RaceError::AlreadyExists(race_id.to_string()).into()),
+            Some(true) => Err(RaceError::InTrash(race_id.to_string()).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl RaceStore for SqliteStore {
+
+    async fn list(&self, archived: bool) -> Result<Vec<Race>> {
+        self.blocking(move |conn| {
+            let mut stmt = conn.prepare("SELECT id, data FROM races WHERE archived = ?1 AND deleted_at IS NULL ORDER BY start_time DESC")?;
+            let rows = stmt.query_map(params![archived], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+            let mut res = Vec::new();
+            for row in rows {
+                let (id, data) = row?;
+                match Self::from_row(&id, archived, &data) {
+                    Ok(race) => res.push(race),
+                    Err(e) => error!("Error reading race {} : {:?}", id, e),
+                }
+            }
+            Ok(res)
+        }).await
+    }
+
+    async fn get(&self, race_id: &RaceId) -> Result<Option<Race>> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            let row = conn.query_row(
+                "SELECT archived, data FROM races WHERE id = ?1 AND deleted_at IS NULL",
+                params![race_id.as_str()],
+                |row| Ok((row.get::<_, bool>(0)?, row.get::<_, String>(1)?)))
+                .optional()?;
+
+            match row {
+                Some((archived, data)) => Ok(Some(Self::from_row(race_id.as_str(), archived, &data)?)),
+                None => Ok(None),
+            }
+        }).await
+    }
+
+    async fn create(&self, race_id: &RaceId, race: &Race) -> Result<()> {
+        let (race_id, race) = (race_id.clone(), race.clone());
+        self.blocking(move |conn| {
+            let (start_time, data) = Self::to_row(&race)?;
+            let tx = conn.transaction()?;
+            Self::available(&tx, &race_id)?;
+            tx.execute(
+                "INSERT INTO races (id, archived, start_time, data) VALUES (?1, 0, ?2, ?3)",
+                params![race_id.as_str(), start_time, data])?;
+            tx.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn update(&self, race_id: &RaceId, new_id: &RaceId, race: &Race) -> Result<()> {
+        let (race_id, new_id, race) = (race_id.clone(), new_id.clone(), race.clone());
+        self.blocking(move |conn| {
+            let (start_time, data) = Self::to_row(&race)?;
+            let tx = conn.transaction()?;
+            if Self::is_archived(&tx, &race_id)? != Some(false) {
+                return Err(RaceError::NotFound(race_id.to_string()).into())
+            }
+            if new_id != race_id {
+                Self::available(&tx, &new_id)?;
+            }
+            tx.execute(
+                "UPDATE races SET id = ?2, start_time = ?3, data = ?4 WHERE id = ?1",
+                params![race_id.as_str(), new_id.as_str(), start_time, data])?;
+            if new_id != race_id {
+                tx.execute("UPDATE race_revisions SET race_id = ?2 WHERE race_id = ?1", params![race_id.as_str(), new_id.as_str()])?;
+            }
+            tx.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn delete(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            match conn.execute(
+                "UPDATE races SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+                params![race_id.as_str(), Utc::now().to_rfc3339()])? {
+                0 => Err(RaceError::NotFound(race_id.to_string()).into()),
+                _ => Ok(()),
+            }
+        }).await
+    }
+
+    async fn archive(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            match conn.execute("UPDATE races SET archived = 1 WHERE id = ?1 AND archived = 0 AND deleted_at IS NULL", params![race_id.as_str()])? {
+                0 => Err(RaceError::NotFound(race_id.to_string()).into()),
+                _ => Ok(()),
+            }
+        }).await
+    }
+
+    async fn restore(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            match conn.execute("UPDATE races SET archived = 0 WHERE id = ?1 AND archived = 1 AND deleted_at IS NULL", params![race_id.as_str()])? {
+                0 => Err(RaceError::NotFound(race_id.to_string()).into()),
+                _ => Ok(()),
+            }
+        }).await
+    }
+
+    async fn add_revision(&self, race_id: &RaceId, revision: &Revision) -> Result<()> {
+        let (race_id, revision) = (race_id.clone(), revision.clone());
+        self.blocking(move |conn| {
+            let data = serde_json::to_string(&revision)?;
+            conn.execute(
+                "INSERT INTO race_revisions (race_id, number, data) VALUES (?1, ?2, ?3)",
+                params![race_id.as_str(), revision.number, data])?;
+            Ok(())
+        }).await
+    }
+
+    async fn revisions(&self, race_id: &RaceId) -> Result<Vec<Revision>> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            let mut stmt = conn.prepare("SELECT data FROM race_revisions WHERE race_id = ?1 ORDER BY number")?;
+            let rows = stmt.query_map(params![race_id.as_str()], |row| row.get::<_, String>(0))?;
+
+            let mut res = Vec::new();
+            for row in rows {
+                let mut revision: Revision = serde_json::from_str(&row?)?;
+                revision.race.id = Some(race_id.clone());
+                res.push(revision);
+            }
+            Ok(res)
+        }).await
+    }
+
+    async fn trash(&self) -> Result<Vec<TrashEntry>> {
+        self.blocking(move |conn| {
+            let mut stmt = conn.prepare("SELECT id, archived, deleted_at, data FROM races WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC")?;
+            let rows = stmt.query_map([], |row| Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?)))?;
+
+            let mut res = Vec::new();
+            for row in rows {
+                let (id, archived, deleted_at, data) = row?;
+                res.push(TrashEntry {
+                    deleted_at: DateTime::parse_from_rfc3339(&deleted_at)?.with_timezone(&Utc),
+                    archived,
+                    race: Self::from_row(&id, archived, &data)?,
+                });
+            }
+            Ok(res)
+        }).await
+    }
+
+    async fn untrash(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            match conn.execute("UPDATE races SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL", params![race_id.as_str()])? {
+                0 => Err(RaceError::NotFound(race_id.to_string()).into()),
+                _ => Ok(()),
+            }
+        }).await
+    }
+
+    async fn purge(&self, race_id: &RaceId) -> Result<()> {
+        let race_id = race_id.clone();
+        self.blocking(move |conn| {
+            let tx = conn.transaction()?;
+            if tx.execute("DELETE FROM races WHERE id = ?1 AND deleted_at IS NOT NULL", params![race_id.as_str()])? == 0 {
+                return Err(RaceError::NotFound(race_id.to_string()).into())
+            }
+            tx.execute("DELETE FROM race_revisions WHERE race_id = ?1", params![race_id.as_str()])?;
+            tx.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn migrate(&self) -> Result<usize> {
+        self.blocking(|conn| {
+            let tx = conn.transaction()?;
+            let rows = {
+                let mut stmt = tx.prepare("SELECT id, data FROM races")?;
+                let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+
+            let mut count = 0;
+            for (id, data) in rows {
+                let document: Value = serde_json::from_str(&data)?;
+                if schema::version(&document) < schema::VERSION {
+                    let race = schema::upgrade(document)?;
+                    let (start_time, data) = Self::to_row(&race)?;
+                    tx.execute("UPDATE races SET start_time = ?2, data = ?3 WHERE id = ?1", params![id, start_time, data])?;
+                    count += 1;
+                }
+            }
+            tx.commit()?;
+            Ok(count)
+        }).await
+    }
+}
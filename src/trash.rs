@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use rocket::tokio::time;
+use serde::{Deserialize, Serialize};
+
+use crate::race::{Race, RaceService};
+
+/// A deleted race, kept until it is restored or purged.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct TrashEntry {
+    pub(crate) deleted_at: DateTime<Utc>,
+    /// whether the race was archived when deleted
+    pub(crate) archived: bool,
+    #[serde(with = "crate::schema")]
+    pub(crate) race: Race,
+}
+
+/// Purges, every hour, the races deleted more than `retention_days` ago.
+pub(crate) async fn purge_periodically(race_service: RaceService, retention_days: i64) {
+    let mut interval = time::interval(Duration::from_secs(3600));
+    loop {
+        interval.tick().await;
+        let before = Utc::now() - chrono::Duration::days(retention_days);
+        match race_service.purge_trash(Some(before)).await {
+            Ok(0) => {},
+            Ok(count) => info!("Purged {} races deleted before {}", count, before),
+            Err(e) => error!("Error purging trash : {}", e),
+        }
+    }
+}
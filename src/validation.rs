@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::race::{LatLon, Limits, Race, Waypoint, WaypointKind};
+
+/// A constraint a race doesn't satisfy, located by the JSON pointer of the offending value.
+#[derive(Debug, Clone)]
+pub(crate) struct Violation {
+    pub(crate) path: String,
+    pub(crate) message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} : {}", self.path, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Violations(Vec<Violation>);
+
+impl Violations {
+    fn add<M: Into<String>>(&mut self, path: String, message: M) {
+        self.0.push(Violation { path, message: message.into() });
+    }
+}
+
+/// Checks the structure of a race, returning every violation found, none when it is valid.
+pub(crate) fn validate(race: &Race) -> Vec<Violation> {
+    let mut violations = Violations::default();
+
+    if race.name.trim().is_empty() {
+        violations.add("/name".to_string(), "must not be empty");
+    }
+    if let (Some(start), Some(end)) = (race.start_time, race.end_time) {
+        if end < start {
+            violations.add("/end_time".to_string(), "must not be before start_time");
+        }
+    }
+
+    latlon(&mut violations, "/start", &race.start);
+
+    if race.waypoints.is_empty() {
+        violations.add("/waypoints".to_string(), "must contain at least one waypoint");
+    }
+    for (index, waypoint) in race.waypoints.iter().enumerate() {
+        self::waypoint(&mut violations, &format!("/waypoints/{}", index), waypoint);
+    }
+
+    if let Some(limits) = &race.ice_limits {
+        self::limits(&mut violations, "/ice_limits", limits);
+    }
+
+    violations.0
+}
+
+fn latlon(violations: &mut Violations, path: &str, latlon: &LatLon) {
+    if !(-90.0..=90.0).contains(&latlon.lat) {
+        violations.add(format!("{}/lat", path), "must be between -90 and 90");
+    }
+    if !(-180.0..=360.0).contains(&latlon.lon) {
+        violations.add(format!("{}/lon", path), "must be between -180 and 360");
+    }
+}
+
+fn waypoint(violations: &mut Violations, path: &str, waypoint: &Waypoint) {
+    if waypoint.name.trim().is_empty() {
+        violations.add(format!("{}/name", path), "must not be empty");
+    }
+
+    // a waypoint is either a gate between two points, or a circle around one
+    match (waypoint.radius, waypoint.latlons.len()) {
+        (None, 2) | (Some(_), 1) => {},
+        (None, count) => violations.add(format!("{}/latlons", path), format!("a gate must have 2 points, not {}", count)),
+        (Some(_), count) => violations.add(format!("{}/latlons", path), format!("a waypoint with a radius must have 1 point, not {}", count)),
+    }
+    for (index, point) in waypoint.latlons.iter().enumerate() {
+        latlon(violations, &format!("{}/latlons/{}", path, index), point);
+    }
+    match (waypoint.kind, waypoint.radius) {
+        (WaypointKind::Gate, Some(_)) => violations.add(format!("{}/kind", path), "a gate has no radius"),
+        (WaypointKind::Finish, None) => violations.add(format!("{}/kind", path), "a finish must have a radius"),
+        _ => {},
+    }
+    if waypoint.side.is_some() && waypoint.radius.is_some() {
+        violations.add(format!("{}/side", path), "a circle has no rounding side");
+    }
+
+    for (index, polygon) in waypoint.to_avoid.iter().flat_map(|zones| &zones.0).enumerate() {
+        let path = format!("{}/toAvoid/{}", path, index);
+        ring(violations, &format!("{}/exterior", path), &polygon.exterior);
+        for (index, hole) in polygon.holes.iter().enumerate() {
+            ring(violations, &format!("{}/holes/{}", path, index), hole);
+        }
+    }
+}
+
+fn ring(violations: &mut Violations, path: &str, ring: &[LatLon]) {
+    if ring.len() < 4 {
+        violations.add(path.to_string(), format!("a ring must have at least 4 points, not {}", ring.len()));
+    }
+    for (index, point) in ring.iter().enumerate() {
+        latlon(violations, &format!("{}/{}", path, index), point);
+    }
+    if let (Some(first), Some(last)) = (ring.first(), ring.last()) {
+        if ring.len() > 1 && (first.lat != last.lat || first.lon != last.lon) {
+            violations.add(path.to_string(), "a ring must be closed, its last point being its first one");
+        }
+    }
+}
+
+fn limits(violations: &mut Violations, path: &str, limits: &Limits) {
+    if limits.min_lat > limits.max_lat {
+        violations.add(format!("{}/minLat", path), "must not be greater than maxLat");
+    }
+    for (name, line) in [("north", &limits.north), ("south", &limits.south)] {
+        for (index, point) in line.iter().enumerate() {
+            latlon(violations, &format!("{}/{}/{}", path, name, index), point);
+            if index > 0 && point.lon < line[index - 1].lon {
+                violations.add(format!("{}/{}/{}/lon", path, name, index), "longitudes must be sorted in increasing order");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::race::{LatLon, Limits, MultiPolygon, Polygon, Race, RaceId, Side, Waypoint, WaypointKind};
+
+    use super::validate;
+
+    fn latlon(lat: f64, lon: f64) -> LatLon {
+        LatLon { lat, lon }
+    }
+
+    /// Waypoint with its geometry only.
+    fn waypoint(name: &str, kind: WaypointKind, radius: Option<u8>, latlons: Vec<LatLon>) -> Waypoint {
+        Waypoint {
+            name: name.to_string(),
+            kind,
+            radius,
+            latlons,
+            side: None,
+            group: None,
+            engine: None,
+            checkpoint: None,
+            to_avoid: None,
+        }
+    }
+
+    /// Race breaking none of the rules, each test breaking one of them.
+    fn race() -> Race {
+        Race {
+            id: Some(RaceId::parse("test").unwrap()),
+            race_id: None,
+            archived: false,
+            name: String::from("Test"),
+            short_name: None,
+            boat: String::from("imoca"),
+            start_time: None,
+            end_time: None,
+            start: latlon(46.5, -1.8),
+            waypoints: vec![
+                waypoint("1", WaypointKind::Gate, None, vec![latlon(45.0, -4.0), latlon(45.0, -5.0)]),
+                waypoint("2", WaypointKind::Buoy, Some(5), vec![latlon(43.0, -9.5)]),
+                waypoint("end", WaypointKind::Finish, Some(1), vec![latlon(46.4, -1.9)]),
+            ],
+            ice_limits: None,
+        }
+    }
+
+    fn paths(race: &Race) -> Vec<String> {
+        validate(race).into_iter().map(|violation| violation.path).collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_race() {
+        assert!(validate(&race()).is_empty());
+    }
+
+    #[test]
+    fn locates_violations_with_json_pointers() {
+        let mut race = race();
+        race.start.lat = 91.0;
+        race.waypoints[0].latlons.pop();
+        race.waypoints[1].latlons[0].lon = -190.0;
+        race.waypoints[1].to_avoid = Some(MultiPolygon(vec![Polygon {
+            exterior: vec![latlon(44.0, -8.0), latlon(44.0, -7.0), latlon(43.0, -7.0), latlon(43.0, -8.0)],
+            holes: Vec::new(),
+        }]));
+        race.waypoints[2].radius = None;
+        race.waypoints[2].latlons.push(latlon(46.5, -1.9));
+
+        assert_eq!(paths(&race), [
+            "/start/lat",
+            "/waypoints/0/latlons",
+            "/waypoints/1/latlons/0/lon",
+            "/waypoints/1/toAvoid/0/exterior",
+            "/waypoints/2/kind",
+        ]);
+    }
+
+    #[test]
+    fn requires_waypoints() {
+        let mut race = race();
+        race.waypoints.clear();
+
+        assert_eq!(paths(&race), ["/waypoints"]);
+    }
+
+    #[test]
+    fn rejects_gates_with_a_radius_and_circles_with_a_side() {
+        let mut race = race();
+        race.waypoints[0] = waypoint("1", WaypointKind::Gate, Some(1), vec![latlon(45.0, -4.0)]);
+        race.waypoints[1].side = Some(Side::Port);
+
+        assert_eq!(paths(&race), ["/waypoints/0/kind", "/waypoints/1/side"]);
+    }
+
+    #[test]
+    fn checks_the_order_of_ice_limits() {
+        let mut race = race();
+        race.ice_limits = Some(Limits {
+            north: vec![latlon(-40.0, -180.0), latlon(-41.0, 10.0), latlon(-40.0, 0.0)],
+            south: vec![latlon(-60.0, -180.0), latlon(-60.0, 180.0)],
+            max_lat: -50.0,
+            min_lat: -40.0,
+        });
+
+        assert_eq!(paths(&race), ["/ice_limits/minLat", "/ice_limits/north/2/lon"]);
+    }
+}
@@ -0,0 +1,41 @@
+use std::io::Write;
+
+use anyhow::Result;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+
+/// Writer of the indented XML documents a race is exported as.
+pub(crate) fn writer() -> Result<Writer<Vec<u8>>> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+    Ok(writer)
+}
+
+pub(crate) fn start<W: Write>(writer: &mut Writer<W>, name: &str) -> Result<()> {
+    writer.write_event(Event::Start(BytesStart::borrowed_name(name.as_bytes())))?;
+    Ok(())
+}
+
+pub(crate) fn start_with<W: Write>(writer: &mut Writer<W>, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+    let element = BytesStart::borrowed_name(name.as_bytes()).with_attributes(attributes.iter().copied());
+    writer.write_event(Event::Start(element))?;
+    Ok(())
+}
+
+pub(crate) fn empty_with<W: Write>(writer: &mut Writer<W>, name: &str, attributes: &[(&str, &str)]) -> Result<()> {
+    let element = BytesStart::borrowed_name(name.as_bytes()).with_attributes(attributes.iter().copied());
+    writer.write_event(Event::Empty(element))?;
+    Ok(())
+}
+
+pub(crate) fn end<W: Write>(writer: &mut Writer<W>, name: &str) -> Result<()> {
+    writer.write_event(Event::End(BytesEnd::borrowed(name.as_bytes())))?;
+    Ok(())
+}
+
+/// Writes an element holding only text, escaped.
+pub(crate) fn text<W: Write>(writer: &mut Writer<W>, name: &str, value: &str) -> Result<()> {
+    start(writer, name)?;
+    writer.write_event(Event::Text(BytesText::from_plain_str(value)))?;
+    end(writer, name)
+}
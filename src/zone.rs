@@ -0,0 +1,30 @@
+use crate::geo;
+use crate::race::{LatLon, Race};
+
+/// Zone to avoid that a position is in, or that a segment crosses.
+#[derive(Debug)]
+pub(crate) struct Intersection {
+    /// index of the waypoint the zone belongs to
+    pub(crate) waypoint: usize,
+    pub(crate) name: String,
+    /// index of the zone among the zones of the waypoint
+    pub(crate) index: usize,
+}
+
+/// Zones to avoid of the race that the position `from` is in, or that the segment from `from` to
+/// `to` crosses.
+pub(crate) fn intersections(race: &Race, from: &LatLon, to: Option<&LatLon>) -> Vec<Intersection> {
+    let mut res = Vec::new();
+    for (waypoint_index, waypoint) in race.waypoints.iter().enumerate() {
+        for (index, polygon) in waypoint.to_avoid.iter().flat_map(|zones| &zones.0).enumerate() {
+            let intersects = match to {
+                Some(to) => geo::polygon_intersects(polygon, from, to),
+                None => geo::polygon_contains(polygon, from),
+            };
+            if intersects {
+                res.push(Intersection { waypoint: waypoint_index, name: waypoint.name.clone(), index });
+            }
+        }
+    }
+    res
+}